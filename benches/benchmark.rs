@@ -0,0 +1,421 @@
+use std::{sync::Arc, thread};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use l3queue::{
+    crs_queue::CrsQueue,
+    he_queue::{BackoffPolicy, HeQueue},
+    lq::LinkedQueue,
+    mutex_queue::MutexQueue,
+    sharded_queue::ShardedQueue,
+};
+
+const ITEMS: u64 = 4096;
+// enough that spawning the producers doesn't drown out their pushes
+const PUSH_ITEMS: u64 = 1 << 16;
+
+// the operations shared by every queue under bench
+trait Queue<T>: Default {
+    fn push(&self, item: T);
+    fn pop(&self) -> Option<T>;
+}
+
+macro_rules! impl_queue {
+    ($($q:ident),*) => {
+        $(
+            impl<T> Queue<T> for $q<T> {
+                fn push(&self, item: T) {
+                    $q::push(self, item)
+                }
+                fn pop(&self) -> Option<T> {
+                    $q::pop(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_queue!(LinkedQueue, MutexQueue, CrsQueue, HeQueue, ShardedQueue);
+
+fn single_insert<Q: Queue<u64>>(c: &mut Criterion, name: &str) {
+    let q = Q::default();
+    let mut i = 0;
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            q.push(i);
+            i += 1;
+        })
+    });
+}
+
+// push then pop `ITEMS` items on a single thread
+fn throughput<Q: Queue<u64>>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Elements(ITEMS));
+    group.bench_function(name, |b| {
+        let q = Q::default();
+        b.iter(|| {
+            for i in 0..ITEMS {
+                q.push(i);
+            }
+            while q.pop().is_some() {}
+        })
+    });
+    group.finish();
+}
+
+// `producers` threads push `ITEMS` items in total while one consumer pops them all
+fn contention<Q: Queue<u64> + Send + Sync + 'static>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(format!("contention/{}", name));
+    group.throughput(Throughput::Elements(ITEMS));
+    for producers in [1u64, 2, 4] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(producers),
+            &producers,
+            |b, &producers| {
+                b.iter(|| {
+                    let q = Arc::new(Q::default());
+                    let handles: Vec<_> = (0..producers)
+                        .map(|n| {
+                            let p = q.clone();
+                            let per = ITEMS / producers;
+                            thread::spawn(move || {
+                                for i in (n * per)..((n + 1) * per) {
+                                    p.push(i);
+                                }
+                            })
+                        })
+                        .collect();
+                    let mut popped = 0;
+                    while popped < ITEMS {
+                        if q.pop().is_some() {
+                            popped += 1;
+                        }
+                    }
+                    for h in handles {
+                        h.join().unwrap();
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// `threads` producers and as many consumers hammering one queue,
+// where the cost of the shared length counter shows up
+fn mpmc<Q: Queue<u64> + Send + Sync + 'static>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(format!("mpmc/{}", name));
+    group.throughput(Throughput::Elements(ITEMS));
+    for threads in [2u64, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    let q = Arc::new(Q::default());
+                    let per = ITEMS / threads;
+                    let producers: Vec<_> = (0..threads)
+                        .map(|n| {
+                            let p = q.clone();
+                            thread::spawn(move || {
+                                for i in (n * per)..((n + 1) * per) {
+                                    p.push(i);
+                                }
+                            })
+                        })
+                        .collect();
+                    let consumers: Vec<_> = (0..threads)
+                        .map(|_| {
+                            let c = q.clone();
+                            thread::spawn(move || {
+                                let mut popped = 0;
+                                while popped < per {
+                                    if c.pop().is_some() {
+                                        popped += 1;
+                                    }
+                                }
+                            })
+                        })
+                        .collect();
+                    for h in producers.into_iter().chain(consumers) {
+                        h.join().unwrap();
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// `producers` threads pushing `PUSH_ITEMS` items in total and nothing else,
+// so only the cost of getting onto a tail shows
+fn push_only<Q: Queue<u64> + Send + Sync + 'static>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(format!("push_only/{}", name));
+    group.throughput(Throughput::Elements(PUSH_ITEMS));
+    for producers in [2u64, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(producers),
+            &producers,
+            |b, &producers| {
+                b.iter_batched(
+                    || Arc::new(Q::default()),
+                    |q| {
+                        let per = PUSH_ITEMS / producers;
+                        let handles: Vec<_> = (0..producers)
+                            .map(|n| {
+                                let p = q.clone();
+                                thread::spawn(move || {
+                                    for i in (n * per)..((n + 1) * per) {
+                                        p.push(i);
+                                    }
+                                })
+                            })
+                            .collect();
+                        for h in handles {
+                            h.join().unwrap();
+                        }
+                        q
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+// 16 producers fighting over `HeQueue`'s tail under each backoff policy
+fn backoff_benches(c: &mut Criterion) {
+    let producers = 16u64;
+    let per = ITEMS / producers;
+
+    let mut group = c.benchmark_group("backoff/he");
+    group.throughput(Throughput::Elements(ITEMS));
+    for policy in [BackoffPolicy::Spin, BackoffPolicy::SpinThenYield] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{:?}", policy)),
+            &policy,
+            |b, &policy| {
+                b.iter(|| {
+                    let q = Arc::new(HeQueue::with_backoff(policy));
+                    let handles: Vec<_> = (0..producers)
+                        .map(|n| {
+                            let p = q.clone();
+                            thread::spawn(move || {
+                                for i in (n * per)..((n + 1) * per) {
+                                    p.push(i);
+                                }
+                            })
+                        })
+                        .collect();
+                    let mut popped = 0;
+                    while popped < ITEMS {
+                        if q.pop().is_some() {
+                            popped += 1;
+                        }
+                    }
+                    for h in handles {
+                        h.join().unwrap();
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// one producer and one consumer on opposite ends of the queue,
+// sensitive to `head` and `tail` sharing a cache line
+fn spsc<Q: Queue<u64> + Send + Sync + 'static>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("spsc");
+    group.throughput(Throughput::Elements(ITEMS));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            let q = Arc::new(Q::default());
+            let p = q.clone();
+            let producer = thread::spawn(move || {
+                for i in 0..ITEMS {
+                    p.push(i);
+                }
+            });
+            let mut popped = 0;
+            while popped < ITEMS {
+                if q.pop().is_some() {
+                    popped += 1;
+                }
+            }
+            producer.join().unwrap();
+        })
+    });
+    group.finish();
+}
+
+fn single_insert_benches(c: &mut Criterion) {
+    single_insert::<LinkedQueue<_>>(c, "single insert lq");
+    single_insert::<MutexQueue<_>>(c, "single insert mq");
+    single_insert::<CrsQueue<_>>(c, "single insert crs");
+    single_insert::<HeQueue<_>>(c, "single insert he");
+}
+
+fn throughput_benches(c: &mut Criterion) {
+    throughput::<LinkedQueue<_>>(c, "lq");
+    throughput::<MutexQueue<_>>(c, "mq");
+    throughput::<CrsQueue<_>>(c, "crs");
+    throughput::<HeQueue<_>>(c, "he");
+}
+
+fn contention_benches(c: &mut Criterion) {
+    contention::<LinkedQueue<_>>(c, "lq");
+    contention::<MutexQueue<_>>(c, "mq");
+    contention::<CrsQueue<_>>(c, "crs");
+    contention::<HeQueue<_>>(c, "he");
+}
+
+// `CrsQueue` still keeps a `SeqCst` counter, a baseline for `HeQueue`'s relaxed one,
+// and for `LinkedQueue`'s hazard pointers against its epochs
+fn mpmc_benches(c: &mut Criterion) {
+    mpmc::<LinkedQueue<_>>(c, "lq");
+    mpmc::<CrsQueue<_>>(c, "crs");
+    mpmc::<HeQueue<_>>(c, "he");
+}
+
+// one tail against a tail per core
+fn sharded_benches(c: &mut Criterion) {
+    push_only::<LinkedQueue<_>>(c, "lq");
+    push_only::<HeQueue<_>>(c, "he");
+    push_only::<ShardedQueue<_>>(c, "sharded");
+    mpmc::<ShardedQueue<_>>(c, "sharded");
+}
+
+fn spsc_benches(c: &mut Criterion) {
+    spsc::<LinkedQueue<_>>(c, "lq");
+    spsc::<CrsQueue<_>>(c, "crs");
+    spsc::<HeQueue<_>>(c, "he");
+
+    // the same workload through `split_spsc`, skipping CAS and pinning
+    let mut group = c.benchmark_group("spsc");
+    group.throughput(Throughput::Elements(ITEMS));
+    group.bench_function("he split", |b| {
+        b.iter(|| {
+            let (mut tx, mut rx) = HeQueue::new().split_spsc();
+            let producer = thread::spawn(move || {
+                for i in 0..ITEMS {
+                    tx.push(i);
+                }
+            });
+            let mut popped = 0;
+            while popped < ITEMS {
+                if rx.pop().is_some() {
+                    popped += 1;
+                }
+            }
+            producer.join().unwrap();
+        })
+    });
+    group.finish();
+}
+
+// consuming a queue we own vs popping it empty
+fn into_vec_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("he_queue/into_vec");
+    group.throughput(Throughput::Elements(ITEMS));
+    let seeded = || (0..ITEMS).collect::<HeQueue<_>>();
+    group.bench_function("into_vec", |b| {
+        b.iter_batched(seeded, |q| q.into_vec(), BatchSize::SmallInput)
+    });
+    group.bench_function("pop loop", |b| {
+        b.iter_batched(
+            seeded,
+            |q| {
+                let mut out = Vec::with_capacity(ITEMS as usize);
+                while let Some(i) = q.pop() {
+                    out.push(i);
+                }
+                out
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+// a pin per pop vs one guard re-pinned every so often
+fn consumer_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crs_queue/pop");
+    group.throughput(Throughput::Elements(ITEMS));
+    let seeded = || {
+        let q = CrsQueue::new();
+        (0..ITEMS).for_each(|i| q.push(i));
+        q
+    };
+    group.bench_function("pop", |b| {
+        b.iter_batched(
+            seeded,
+            |q| while q.pop().is_some() {},
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("consumer", |b| {
+        b.iter_batched(
+            seeded,
+            |q| {
+                let mut c = q.consumer();
+                while c.pop().is_some() {}
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+// what an `is_empty` short-circuit in front of `pop` would cost a
+// single consumer that already knows the queue is non-empty
+fn pop_check_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("he_queue/pop");
+    group.throughput(Throughput::Elements(ITEMS));
+    let seeded = || (0..ITEMS).collect::<HeQueue<_>>();
+    group.bench_function("pop", |b| {
+        b.iter_batched(
+            seeded,
+            |q| while q.pop().is_some() {},
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("is_empty then pop", |b| {
+        b.iter_batched(
+            seeded,
+            |q| while !q.is_empty() && q.pop().is_some() {},
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+// run once as is and once with `--features he-no-len`,
+// criterion then reports the second run against the first
+fn len_counter_benches(c: &mut Criterion) {
+    mpmc::<HeQueue<_>>(c, "he len counter");
+}
+
+// likewise with `--features dwcas`, for `LinkedQueue`'s 16 byte head;
+// `mpmc_benches` has `CrsQueue` and `HeQueue` to hold it against
+fn head_benches(c: &mut Criterion) {
+    mpmc::<LinkedQueue<_>>(c, "lq head");
+}
+
+criterion_group!(
+    benches,
+    single_insert_benches,
+    throughput_benches,
+    contention_benches,
+    mpmc_benches,
+    backoff_benches,
+    sharded_benches,
+    spsc_benches,
+    into_vec_benches,
+    consumer_benches,
+    pop_check_benches,
+    len_counter_benches,
+    head_benches
+);
+criterion_main!(benches);
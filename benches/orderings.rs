@@ -0,0 +1,76 @@
+use std::{sync::Arc, thread};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use l3queue::he_queue::{HeQueue, OrderingProfile};
+
+const ITEMS: u64 = 4096;
+const PROFILES: [OrderingProfile; 2] = [OrderingProfile::Strong, OrderingProfile::Relaxed];
+
+// one thread pushing and popping, polling `len` like a monitor would
+fn single_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("he_queue/orderings/single");
+    group.throughput(Throughput::Elements(ITEMS));
+
+    for orderings in PROFILES {
+        let name = format!("{:?}", orderings);
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            let q = HeQueue::with_orderings(orderings);
+            b.iter(|| {
+                for i in 0..ITEMS {
+                    q.push(i);
+                }
+                while !q.is_empty() {
+                    q.pop();
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+// two producers and two consumers sharing one queue
+fn mpmc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("he_queue/orderings/mpmc");
+    group.throughput(Throughput::Elements(ITEMS * 2));
+
+    for orderings in PROFILES {
+        let name = format!("{:?}", orderings);
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            let q = Arc::new(HeQueue::with_orderings(orderings));
+            b.iter(|| {
+                let producers: Vec<_> = (0..2)
+                    .map(|_| {
+                        let q = q.clone();
+                        thread::spawn(move || {
+                            for i in 0..ITEMS {
+                                q.push(i);
+                            }
+                        })
+                    })
+                    .collect();
+                let consumers: Vec<_> = (0..2)
+                    .map(|_| {
+                        let q = q.clone();
+                        thread::spawn(move || {
+                            let mut popped = 0;
+                            while popped < ITEMS {
+                                if q.pop().is_some() {
+                                    popped += 1;
+                                }
+                            }
+                        })
+                    })
+                    .collect();
+                for h in producers.into_iter().chain(consumers) {
+                    h.join().unwrap();
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, single_thread, mpmc);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use crossbeam_epoch as epoch;
+use l3queue::{he_queue::HeQueue, lq::LinkedQueue};
+
+const ITEMS: u64 = 4096;
+// batch size for `guard_reuse`
+const BATCH: u64 = 1024;
+
+// per-item cost of pushing `ITEMS` items in batches of `size`
+fn push_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("he_queue/push_batch");
+    group.throughput(Throughput::Elements(ITEMS));
+
+    for size in [1u64, 16, 256] {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            let q = HeQueue::new();
+            b.iter(|| {
+                for n in 0..(ITEMS / size) {
+                    q.push_batch((n * size)..((n + 1) * size));
+                }
+                while q.pop().is_some() {}
+            });
+        });
+    }
+    group.bench_function("push", |b| {
+        let q = HeQueue::new();
+        b.iter(|| {
+            for i in 0..ITEMS {
+                q.push(i);
+            }
+            while q.pop().is_some() {}
+        });
+    });
+
+    group.finish();
+}
+
+// the same for `LinkedQueue`, where a batch is linked with a single CAS
+fn lq_push_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lq/push_batch");
+    group.throughput(Throughput::Elements(ITEMS));
+
+    for size in [16u64, 256, 4096] {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            let q = LinkedQueue::new();
+            b.iter(|| {
+                for n in 0..(ITEMS / size) {
+                    q.push_batch((n * size)..((n + 1) * size));
+                }
+                while q.pop().is_some() {}
+            });
+        });
+    }
+    group.bench_function("push", |b| {
+        let q = LinkedQueue::new();
+        b.iter(|| {
+            for i in 0..ITEMS {
+                q.push(i);
+            }
+            while q.pop().is_some() {}
+        });
+    });
+
+    group.finish();
+}
+
+// per-op cost of pinning once per operation vs once per batch
+fn guard_reuse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("he_queue/guard_reuse");
+    group.throughput(Throughput::Elements(BATCH));
+
+    group.bench_function("pin per op", |b| {
+        let q = HeQueue::new();
+        b.iter(|| {
+            for i in 0..BATCH {
+                q.push(i);
+            }
+            while q.pop().is_some() {}
+        });
+    });
+    group.bench_function("pin per batch", |b| {
+        let q = HeQueue::new();
+        b.iter(|| {
+            let guard = &epoch::pin();
+            for i in 0..BATCH {
+                q.push_with(i, guard);
+            }
+            while q.pop_with(guard).is_some() {}
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, push_batch, lq_push_batch, guard_reuse);
+criterion_main!(benches);
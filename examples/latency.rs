@@ -0,0 +1,176 @@
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use l3queue::{crs_queue::CrsQueue, he_queue::HeQueue, lq::LinkedQueue, mutex_queue::MutexQueue};
+
+// sub-buckets per power of two, keeps the relative error around 1/16
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+
+// a simple log-linear bucketed histogram of nanoseconds
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: vec![0; 64 * SUB_BUCKETS],
+            count: 0,
+        }
+    }
+
+    fn index(v: u64) -> usize {
+        if v < SUB_BUCKETS as u64 {
+            return v as usize;
+        }
+        let exp = 63 - v.leading_zeros();
+        let sub = (v >> (exp - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+        (exp - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+    }
+
+    // lower bound of the values falling into bucket `idx`
+    fn value(idx: usize) -> u64 {
+        if idx < SUB_BUCKETS {
+            return idx as u64;
+        }
+        let exp = (idx / SUB_BUCKETS) as u32 + SUB_BITS - 1;
+        let sub = (idx % SUB_BUCKETS) as u64;
+        (1 << exp) | (sub << (exp - SUB_BITS))
+    }
+
+    fn record(&mut self, d: Duration) {
+        let ns = d.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[Self::index(ns)] += 1;
+        self.count += 1;
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let target = ((self.count as f64) * p / 100f64).ceil().max(1f64) as u64;
+        let mut seen = 0;
+        for (idx, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= target {
+                return Self::value(idx);
+            }
+        }
+        Self::value(self.buckets.len() - 1)
+    }
+}
+
+// the operations shared by every queue under test
+trait Queue<T> {
+    fn push(&self, item: T);
+    fn pop(&self) -> Option<T>;
+}
+
+macro_rules! impl_queue {
+    ($($q:ident),*) => {
+        $(
+            impl<T> Queue<T> for $q<T> {
+                fn push(&self, item: T) {
+                    $q::push(self, item)
+                }
+                fn pop(&self) -> Option<T> {
+                    $q::pop(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_queue!(LinkedQueue, MutexQueue, CrsQueue, HeQueue);
+
+// runs one producer and one consumer against `q` for `du`,
+// timing every single push and every successful pop
+fn measure<Q: Queue<u128> + Send + Sync + 'static>(q: Q, du: Duration) -> (Histogram, Histogram) {
+    let q = Arc::new(q);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let p = q.clone();
+    let p_stop = stop.clone();
+    let producer = thread::spawn(move || {
+        let mut hist = Histogram::new();
+        let mut i = 0u128;
+        while !p_stop.load(Ordering::Relaxed) {
+            let start = Instant::now();
+            p.push(i);
+            hist.record(start.elapsed());
+            i += 1;
+        }
+        hist
+    });
+
+    let c = q;
+    let c_stop = stop.clone();
+    let consumer = thread::spawn(move || {
+        let mut hist = Histogram::new();
+        while !c_stop.load(Ordering::Relaxed) {
+            let start = Instant::now();
+            if c.pop().is_some() {
+                hist.record(start.elapsed());
+            }
+        }
+        hist
+    });
+
+    thread::sleep(du);
+    stop.store(true, Ordering::Relaxed);
+
+    (producer.join().unwrap(), consumer.join().unwrap())
+}
+
+fn report(name: &str, op: &str, hist: &Histogram) {
+    println!(
+        "{},{},{},{},{},{}",
+        name,
+        op,
+        hist.count,
+        hist.percentile(50f64),
+        hist.percentile(99f64),
+        hist.percentile(99.9f64)
+    );
+}
+
+fn main() {
+    // duration per queue in seconds, from the first argument or `LATENCY_SECS`
+    let secs = env::args()
+        .nth(1)
+        .or_else(|| env::var("LATENCY_SECS").ok())
+        .map(|s| {
+            s.parse::<u64>()
+                .expect("duration must be a number of seconds")
+        })
+        .unwrap_or(5);
+    let du = Duration::from_secs(secs);
+
+    println!("measuring each queue for {}s...", secs);
+    println!("queue,op,samples,p50_ns,p99_ns,p999_ns");
+
+    let (push, pop) = measure(LinkedQueue::new(), du);
+    report("lq", "push", &push);
+    report("lq", "pop", &pop);
+
+    let (push, pop) = measure(MutexQueue::new(), du);
+    report("mq", "push", &push);
+    report("mq", "pop", &pop);
+
+    let (push, pop) = measure(CrsQueue::new(), du);
+    report("cq", "push", &push);
+    report("cq", "pop", &pop);
+
+    let (push, pop) = measure(HeQueue::new(), du);
+    report("hq", "push", &push);
+    report("hq", "pop", &pop);
+}
@@ -1,114 +1,246 @@
 use std::{
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
-    thread,
+    thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
 use charts::{AxisPosition, Chart, Color, LineSeriesView, MarkerType, ScaleLinear};
-use l3queue::{crs_queue::CrsQueue, lq::LinkedQueue, mutex_queue::MutexQueue};
+use l3queue::{
+    crs_queue::CrsQueue, he_queue::HeQueue, lq::LinkedQueue, mutex_queue::MutexQueue,
+    priority_queue::PriorityQueue, ring_queue::RingQueue,
+};
+
+// run with `--features stats` to also print `HeQueue`'s contention per epoch
+
+// one queue under test: a producer and a consumer hammering it,
+// and the per-epoch bandwidth sampled from the producer's counter
+struct Series {
+    name: &'static str,
+    label: &'static str,
+    color: &'static str,
+    marker: MarkerType,
+    pushed: Arc<AtomicUsize>,
+    last: usize,
+    bw: Vec<usize>,
+}
+
+impl Series {
+    fn new(
+        name: &'static str,
+        label: &'static str,
+        color: &'static str,
+        marker: MarkerType,
+    ) -> Self {
+        Self {
+            name,
+            label,
+            color,
+            marker,
+            pushed: Arc::new(AtomicUsize::new(0)),
+            last: 0,
+            bw: vec![],
+        }
+    }
+
+    // `push` reports whether the item went in, a bounded queue may refuse it
+    fn spawn<P, C>(
+        self,
+        stop: &Arc<AtomicBool>,
+        threads: &mut Vec<JoinHandle<()>>,
+        mut push: P,
+        mut pop: C,
+    ) -> Self
+    where
+        P: FnMut(u128) -> bool + Send + 'static,
+        C: FnMut() + Send + 'static,
+    {
+        let stop1 = stop.clone();
+        let pushed1 = self.pushed.clone();
+        threads.push(thread::spawn(move || {
+            for i in 0u128.. {
+                if stop1.load(Ordering::Relaxed) {
+                    break;
+                }
+                if push(i) {
+                    pushed1.fetch_add(1, Ordering::Release);
+                }
+            }
+        }));
+        let stop1 = stop.clone();
+        threads.push(thread::spawn(move || {
+            while !stop1.load(Ordering::Relaxed) {
+                pop();
+            }
+        }));
+        self
+    }
+
+    fn baseline(&mut self) {
+        self.last = self.pushed.load(Ordering::Acquire);
+    }
+
+    fn sample(&mut self) -> usize {
+        let now = self.pushed.load(Ordering::Acquire);
+        let bw = now - self.last;
+        self.last = now;
+        self.bw.push(bw);
+        bw
+    }
+}
 
 fn main() {
     let _du = 30;
-    let begin = Instant::now();
-    let du = Duration::from_secs(_du);
     let epoch = Duration::from_secs(1);
-    let ddl = begin + du;
+    // let the threads get scheduled and the queues settle before the baseline
+    let warm_up = Duration::from_secs(1);
+    let ring_capacity = 1 << 16;
 
-    let p_lq_cnt = Arc::new(AtomicUsize::new(0));
-    let p_lq_cnt1 = p_lq_cnt.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut threads = vec![];
 
-    let p_mq_cnt = Arc::new(AtomicUsize::new(0));
-    let p_mq_cnt1 = p_mq_cnt.clone();
+    let lq = Arc::new(LinkedQueue::new());
+    let mq = Arc::new(MutexQueue::new());
+    let cq = Arc::new(CrsQueue::new());
+    let hq = Arc::new(HeQueue::new());
+    let rq = Arc::new(RingQueue::new(ring_capacity));
+    let pq = Arc::new(PriorityQueue::new());
+    #[cfg(feature = "stats")]
+    let s_hq = hq.clone();
 
-    let p_cq_cnt = Arc::new(AtomicUsize::new(0));
-    let p_cq_cnt1 = p_cq_cnt.clone();
+    let (p_lq, c_lq) = (lq.clone(), lq);
+    let (p_mq, c_mq) = (mq.clone(), mq);
+    let (p_cq, c_cq) = (cq.clone(), cq);
+    let (p_hq, c_hq) = (hq.clone(), hq);
+    let (p_rq, c_rq) = (rq.clone(), rq);
+    let (p_pq, c_pq) = (pq.clone(), pq);
+    let mut series = [
+        Series::new("lq", "手写链表实现", "#FF4700", MarkerType::Circle).spawn(
+            &stop,
+            &mut threads,
+            move |i| {
+                p_lq.push(i);
+                true
+            },
+            move || {
+                c_lq.pop();
+            },
+        ),
+        Series::new("cq", "Crossbeam GC 链表实现", "#47FF00", MarkerType::Square).spawn(
+            &stop,
+            &mut threads,
+            move |i| {
+                p_cq.push(i);
+                true
+            },
+            move || {
+                c_cq.pop();
+            },
+        ),
+        Series::new("mq", "链表加大锁实现", "#0047FF", MarkerType::X).spawn(
+            &stop,
+            &mut threads,
+            move |i| {
+                p_mq.push(i);
+                true
+            },
+            move || {
+                c_mq.pop();
+            },
+        ),
+        Series::new(
+            "hq",
+            "Crossbeam GC 严格尾指针实现",
+            "#B000FF",
+            MarkerType::Circle,
+        )
+        .spawn(
+            &stop,
+            &mut threads,
+            move |i| {
+                p_hq.push(i);
+                true
+            },
+            move || {
+                c_hq.pop();
+            },
+        ),
+        // only accepted pushes count, a full ring turns the producer away
+        Series::new("rq", "定长环形加锁实现", "#FFB000", MarkerType::Square).spawn(
+            &stop,
+            &mut threads,
+            move |i| p_rq.try_push(i).is_ok(),
+            move || {
+                c_rq.pop();
+            },
+        ),
+        Series::new("pq", "二叉堆优先队列实现", "#00B0B0", MarkerType::X).spawn(
+            &stop,
+            &mut threads,
+            move |i| {
+                p_pq.push(i);
+                true
+            },
+            move || {
+                c_pq.pop();
+            },
+        ),
+    ];
 
-    let p_lq = Arc::new(LinkedQueue::new());
-    let c_lq = p_lq.clone();
-    let p_mq = Arc::new(MutexQueue::new());
-    let c_mq = p_mq.clone();
-    let p_cq = Arc::new(CrsQueue::new());
-    let c_cq = p_cq.clone();
+    thread::sleep(warm_up);
+    let begin = Instant::now();
+    for s in series.iter_mut() {
+        s.baseline();
+    }
+    #[cfg(feature = "stats")]
+    s_hq.reset_stats();
 
-    let _t1 = thread::spawn(move || {
-        for i in 0u128.. {
-            p_lq.push(i);
-            p_lq_cnt1.fetch_add(1, Ordering::Release);
-        }
-    });
-    let _t2 = thread::spawn(move || loop {
-        loop {
-            c_lq.pop();
-        }
-    });
-    let _t3 = thread::spawn(move || {
-        for i in 0u128.. {
-            p_mq.push(i);
-            p_mq_cnt1.fetch_add(1, Ordering::Release);
-        }
-    });
-    let _t4 = thread::spawn(move || loop {
-        loop {
-            c_mq.pop();
-        }
-    });
+    println!("start recording...");
+    let header: Vec<_> = series.iter().map(|s| format!("bw_{}", s.name)).collect();
+    #[cfg(not(feature = "stats"))]
+    println!("time,{}", header.join(","));
+    #[cfg(feature = "stats")]
+    println!(
+        "time,{},hq_push_fail_rate,hq_tail_helps,hq_pop_retries",
+        header.join(",")
+    );
+    // exactly one sample per epoch, matching the chart's x axis
+    for _ in 0.._du {
+        thread::sleep(epoch);
+        let uptime = begin.elapsed().as_secs();
 
-    let _t5 = thread::spawn(move || {
-        for i in 0u128.. {
-            p_cq.push(i);
-            p_cq_cnt1.fetch_add(1, Ordering::Release);
-        }
-    });
-    let _t6 = thread::spawn(move || loop {
-        loop {
-            c_cq.pop();
+        let bw: Vec<_> = series.iter_mut().map(|s| s.sample().to_string()).collect();
+        #[cfg(not(feature = "stats"))]
+        println!("{},{}", uptime, bw.join(","));
+        #[cfg(feature = "stats")]
+        {
+            let stats = s_hq.stats().contention;
+            s_hq.reset_stats();
+            println!(
+                "{},{},{:.4},{},{}",
+                uptime,
+                bw.join(","),
+                stats.push_failure_rate(),
+                stats.tail_helps,
+                stats.pop_retries
+            );
         }
-    });
-    let lq_p = p_lq_cnt.load(Ordering::Acquire);
-    let cq_p = p_cq_cnt.load(Ordering::Acquire);
-    let mq_p = p_mq_cnt.load(Ordering::Acquire);
-
-    let mut lq_full = vec![lq_p];
-    let mut cq_full = vec![cq_p];
-    let mut mq_full = vec![mq_p];
-    let mut lq = vec![];
-    let mut cq = vec![];
-    let mut mq = vec![];
+    }
 
-    println!("start recording...");
-    println!("time,bw_lq,bw_cq,bw_mq");
-    let mut now = Instant::now();
-    while now <= ddl {
-        thread::sleep(epoch);
-        now = Instant::now();
-
-        let uptime = now.duration_since(begin).as_secs();
-
-        let lq_p = p_lq_cnt.load(Ordering::Acquire);
-        let cq_p = p_cq_cnt.load(Ordering::Acquire);
-        let mq_p = p_mq_cnt.load(Ordering::Acquire);
-
-        let bw_lq = lq_p - lq_full[lq_full.len() - 1];
-        let bw_cq = cq_p - cq_full[cq_full.len() - 1];
-        let bw_mq = mq_p - mq_full[mq_full.len() - 1];
-        println!("{},{},{},{}", uptime, bw_lq, bw_cq, bw_mq);
-        lq_full.push(lq_p);
-        cq_full.push(cq_p);
-        mq_full.push(mq_p);
-        lq.push(bw_lq);
-        cq.push(bw_cq);
-        mq.push(bw_mq);
+    stop.store(true, Ordering::Relaxed);
+    for t in threads {
+        t.join().unwrap();
     }
 
-    let max = lq
+    // the y axis has to fit every series, not just the first
+    let max = series
         .iter()
+        .flat_map(|s| s.bw.iter())
+        .copied()
         .max()
-        .unwrap()
-        .max(cq.iter().max().unwrap())
-        .max(mq.iter().max().unwrap());
+        .unwrap();
     let range = max / 5 * 6; // 120%
 
     let width = 800;
@@ -121,54 +253,40 @@ fn main() {
         .set_domain(vec![0f32, range as f32])
         .set_range(vec![height - top - bottom, 0]);
 
-    let lq_data = (1..=_du)
-        .map(|x| x as f32)
-        .zip(lq.iter().map(|&x| x as f32))
-        .collect();
-    let lq_view = LineSeriesView::new()
-        .set_x_scale(&x)
-        .set_y_scale(&y)
-        .set_marker_type(MarkerType::Circle)
-        .set_label_visibility(false)
-        .set_colors(Color::from_vec_of_hex_strings(vec!["#FF4700"]))
-        .set_custom_data_label(String::from("手写链表实现"))
-        .load_data(&lq_data)
-        .unwrap();
-    let cq_data = (1..=_du)
-        .map(|x| x as f32)
-        .zip(cq.iter().map(|&x| x as f32))
+    let data: Vec<Vec<(f32, f32)>> = series
+        .iter()
+        .map(|s| {
+            (1..=_du)
+                .map(|x| x as f32)
+                .zip(s.bw.iter().map(|&x| x as f32))
+                .collect()
+        })
         .collect();
-    let cq_view = LineSeriesView::new()
-        .set_x_scale(&x)
-        .set_y_scale(&y)
-        .set_marker_type(MarkerType::Square)
-        .set_label_visibility(false)
-        .set_colors(Color::from_vec_of_hex_strings(vec!["#47FF00"]))
-        .set_custom_data_label(String::from("Crossbeam GC 链表实现"))
-        .load_data(&cq_data)
-        .unwrap();
-    let mq_data = (1..=_du)
-        .map(|x| x as f32)
-        .zip(mq.iter().map(|&x| x as f32))
+    let views: Vec<_> = series
+        .iter()
+        .zip(data.iter())
+        .map(|(s, d)| {
+            LineSeriesView::new()
+                .set_x_scale(&x)
+                .set_y_scale(&y)
+                .set_marker_type(s.marker)
+                .set_label_visibility(false)
+                .set_colors(Color::from_vec_of_hex_strings(vec![s.color]))
+                .set_custom_data_label(String::from(s.label))
+                .load_data(d)
+                .unwrap()
+        })
         .collect();
-    let mq_view = LineSeriesView::new()
-        .set_x_scale(&x)
-        .set_y_scale(&y)
-        .set_marker_type(MarkerType::X)
-        .set_label_visibility(false)
-        .set_colors(Color::from_vec_of_hex_strings(vec!["#0047FF"]))
-        .set_custom_data_label(String::from("链表加大锁实现"))
-        .load_data(&mq_data)
-        .unwrap();
 
-    Chart::new()
-        .set_width(width)
-        .set_height(height)
-        .set_margins(top, right, bottom, left)
-        .add_title(String::from("带宽测试"))
-        .add_view(&lq_view)
-        .add_view(&cq_view)
-        .add_view(&mq_view)
+    let chart = views.iter().fold(
+        Chart::new()
+            .set_width(width)
+            .set_height(height)
+            .set_margins(top, right, bottom, left)
+            .add_title(String::from("带宽测试")),
+        |chart, view| chart.add_view(view),
+    );
+    chart
         .add_axis_bottom(&x)
         .add_axis_left(&y)
         .add_left_axis_label("带宽（个）")
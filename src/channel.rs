@@ -0,0 +1,410 @@
+// a bounded blocking channel on top of `MutexQueue`
+// the queue's own lock doubles as the condvars' mutex
+
+use std::sync::{Arc, Condvar, Weak};
+
+use crate::mutex_queue::MutexQueue;
+
+struct Shared<T> {
+    queue: MutexQueue<T>,
+    capacity: usize,
+    not_empty: Condvar,
+    not_full: Condvar,
+    // every `Sender`/`Receiver` holds a strong count on its side's token
+    senders: Weak<()>,
+    receivers: Weak<()>,
+}
+
+/// Returned by [`Receiver::recv`] once it can't yield anything more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// Every `Sender` is gone and the channel is drained.
+    Disconnected,
+}
+
+/// Returned by [`Receiver::try_recv`] when there is no item to hand out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// Nothing queued right now, but a `Sender` may still send.
+    Empty,
+    /// Every `Sender` is gone and the channel is drained.
+    Disconnected,
+}
+
+/// Returned by [`Sender::send`] once every `Receiver` is gone, handing the item back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Returned by [`Sender::try_send`] when the item can't go in right now,
+/// handing it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full, but a `Receiver` may still make room.
+    Full(T),
+    /// Every `Receiver` is gone.
+    Disconnected(T),
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(item) | TrySendError::Disconnected(item) => item,
+        }
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    token: Option<Arc<()>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    token: Option<Arc<()>>,
+}
+
+/// Create a channel holding at most `capacity` items.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be positive");
+
+    let senders = Arc::new(());
+    let receivers = Arc::new(());
+    let shared = Arc::new(Shared {
+        queue: MutexQueue::new(),
+        capacity,
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        senders: Arc::downgrade(&senders),
+        receivers: Arc::downgrade(&receivers),
+    });
+    let tx = Sender {
+        shared: shared.clone(),
+        token: Some(senders),
+    };
+    let rx = Receiver {
+        shared,
+        token: Some(receivers),
+    };
+    (tx, rx)
+}
+
+impl<T> Shared<T> {
+    fn remaining_capacity(&self) -> usize {
+        self.capacity.saturating_sub(self.queue.len())
+    }
+}
+
+impl<T> Sender<T> {
+    /// The most items the channel holds at once.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// How many more items fit before `send` blocks.
+    ///
+    /// A racy estimate, other senders and receivers may act right after.
+    pub fn remaining_capacity(&self) -> usize {
+        self.shared.remaining_capacity()
+    }
+
+    /// Push `item`, blocking while the channel is full.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let shared = &*self.shared;
+        let mut guard = shared.queue.inner.lock().unwrap();
+        loop {
+            if shared.receivers.strong_count() == 0 {
+                return Err(SendError(item));
+            }
+            if guard.len() < shared.capacity {
+                guard.push_back(item);
+                shared.not_empty.notify_one();
+                return Ok(());
+            }
+            guard = shared.not_full.wait(guard).unwrap();
+        }
+    }
+
+    /// Push `item` if there is room, without blocking.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        let shared = &*self.shared;
+        let mut guard = shared.queue.inner.lock().unwrap();
+        if shared.receivers.strong_count() == 0 {
+            return Err(TrySendError::Disconnected(item));
+        }
+        if guard.len() >= shared.capacity {
+            return Err(TrySendError::Full(item));
+        }
+        guard.push_back(item);
+        shared.not_empty.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Receiver<T> {
+    /// The most items the channel holds at once.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// How many more items fit before a `send` blocks.
+    ///
+    /// A racy estimate, senders and other receivers may act right after.
+    pub fn remaining_capacity(&self) -> usize {
+        self.shared.remaining_capacity()
+    }
+
+    /// Pop an item, blocking while the channel is empty.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let shared = &*self.shared;
+        let mut guard = shared.queue.inner.lock().unwrap();
+        loop {
+            if let Some(item) = guard.pop_front() {
+                shared.not_full.notify_one();
+                return Ok(item);
+            }
+            if shared.senders.strong_count() == 0 {
+                return Err(RecvError::Disconnected);
+            }
+            guard = shared.not_empty.wait(guard).unwrap();
+        }
+    }
+
+    /// Pop an item if one is queued, without waiting.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let shared = &*self.shared;
+        let mut guard = shared.queue.inner.lock().unwrap();
+        if let Some(item) = guard.pop_front() {
+            shared.not_full.notify_one();
+            return Ok(item);
+        }
+        if shared.senders.strong_count() == 0 {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            token: self.token.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            token: self.token.clone(),
+        }
+    }
+}
+
+// release the token first, then wake the other side under the lock,
+// so a waiter either sees the new count or gets notified
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        drop(self.token.take());
+        // a poisoned lock still hands out its guard
+        let _guard = self.shared.queue.inner.lock();
+        self.shared.not_empty.notify_all();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        drop(self.token.take());
+        let _guard = self.shared.queue.inner.lock();
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::{channel, RecvError, SendError, TryRecvError, TrySendError};
+
+    #[test]
+    fn test_single() {
+        let (tx, rx) = channel(8);
+        for i in [1, 1, 4, 5, 1, 4] {
+            tx.send(i).unwrap();
+        }
+        for i in [1, 1, 4, 5, 1, 4] {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+    }
+
+    #[test]
+    fn test_remaining_capacity() {
+        let capacity = 100;
+        let (tx, rx) = channel(capacity);
+        assert_eq!((tx.capacity(), rx.capacity()), (capacity, capacity));
+        assert_eq!(tx.remaining_capacity(), capacity);
+
+        // two senders fill it halfway while the estimate is sampled
+        let senders: Vec<_> = (0..2)
+            .map(|_| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..capacity / 4 {
+                        tx.send(i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        while senders.iter().any(|s| !s.is_finished()) {
+            let left = rx.remaining_capacity();
+            assert!((capacity / 2..=capacity).contains(&left), "{}", left);
+        }
+        for s in senders {
+            s.join().unwrap();
+        }
+        assert_eq!(tx.remaining_capacity(), capacity / 2);
+        rx.recv().unwrap();
+        assert_eq!(rx.remaining_capacity(), capacity / 2 + 1);
+    }
+
+    #[test]
+    fn test_blocking_send() {
+        let (tx, rx) = channel(1);
+        tx.send(1).unwrap();
+
+        let sent = Arc::new(AtomicBool::new(false));
+        let sent1 = sent.clone();
+        let t = thread::spawn(move || {
+            tx.send(2).unwrap();
+            sent1.store(true, Ordering::SeqCst);
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!sent.load(Ordering::SeqCst));
+        assert_eq!(rx.recv(), Ok(1));
+        t.join().unwrap();
+        assert!(sent.load(Ordering::SeqCst));
+        assert_eq!(rx.recv(), Ok(2));
+    }
+
+    #[test]
+    fn test_blocking_recv() {
+        let (tx, rx) = channel(4);
+        let t = thread::spawn(move || rx.recv());
+
+        thread::sleep(Duration::from_millis(100));
+        tx.send(42).unwrap();
+        assert_eq!(t.join().unwrap(), Ok(42));
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (tx, rx) = channel(4);
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        tx2.send(2).unwrap();
+
+        // a blocked receiver is woken once the last sender goes away
+        let t = thread::spawn(move || {
+            let mut got = vec![];
+            while let Ok(i) = rx.recv() {
+                got.push(i);
+            }
+            got
+        });
+        thread::sleep(Duration::from_millis(100));
+        drop(tx2);
+        assert_eq!(t.join().unwrap(), vec![1, 2]);
+
+        let (tx, rx) = channel::<i32>(1);
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+
+        let (tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    fn test_try_send() {
+        let (tx, rx) = channel(2);
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(4), Ok(()));
+        assert_eq!(tx.try_send(5), Err(TrySendError::Full(5)));
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(tx.try_send(5), Ok(()));
+        drop(rx);
+        let err = tx.try_send(1).unwrap_err();
+        assert_eq!(err, TrySendError::Disconnected(1));
+        assert_eq!(err.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_try_recv() {
+        let (tx, rx) = channel(2);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.send(1).unwrap();
+        tx.send(4).unwrap();
+        assert_eq!(rx.remaining_capacity(), 0);
+        assert_eq!(rx.try_recv(), Ok(1));
+        // a pop frees a slot for a blocked sender too
+        assert_eq!(rx.remaining_capacity(), 1);
+
+        // what's left goes out before the disconnect shows
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_mpmc() {
+        let pad = 10000u128;
+
+        let (tx, rx) = channel(16);
+        let producers: Vec<_> = (0..3)
+            .map(|n| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in (n * pad)..((n + 1) * pad) {
+                        tx.send(i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || {
+                    let mut sum = 0;
+                    while let Ok(i) = rx.recv() {
+                        sum += i;
+                    }
+                    sum
+                })
+            })
+            .collect();
+        drop(rx);
+
+        for p in producers {
+            p.join().unwrap();
+        }
+        let sum: u128 = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+        assert_eq!(sum, (0..(3 * pad)).sum());
+    }
+}
@@ -2,41 +2,45 @@
 // push with casual tail algorithm
 
 use std::{
+    hint,
     io::Write,
-    sync::atomic::{AtomicUsize, Ordering},
+    mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
 };
 
-use crossbeam::epoch;
-use epoch::{Atomic, Owned, Shared};
+use crossbeam_epoch as epoch;
+use epoch::{Atomic, Guard, Owned, Shared};
 
-type NodePtr<T> = Atomic<Node<T>>;
-struct Node<T> {
-    pub item: Option<T>,
-    pub next: NodePtr<T>,
-}
-
-impl<T> Node<T> {
-    pub fn new_empty() -> Self {
-        Self {
-            item: None,
-            next: Atomic::null(),
-        }
-    }
-
-    pub fn new(data: T) -> Self {
-        Self {
-            item: Some(data),
-            next: Atomic::null(),
-        }
-    }
-}
+#[cfg(feature = "metrics")]
+use crate::metrics::QueueStats;
+use crate::{
+    he_queue::HeQueue,
+    metrics::Metrics,
+    node::{Node, NodePtr, PeekGuard, READER, TAKEN},
+    notify::Notify,
+};
 
 pub struct CrsQueue<T> {
     len: AtomicUsize,
     head: NodePtr<T>,
     tail: NodePtr<T>,
+    // empty nodes from `prewarm`, linked through `next`,
+    // each is handed out to one push and never comes back
+    free: NodePtr<T>,
+    // wakes consumers parked in `select_pop`
+    notify: Notify,
+    metrics: Metrics,
 }
 
+// items are only moved out through the TAKEN/READER protocol,
+// and `peek` hands out `&T` to any thread, hence `T: Sync`
+unsafe impl<T: Send> Send for CrsQueue<T> {}
+unsafe impl<T: Send + Sync> Sync for CrsQueue<T> {}
+
 impl<T> Default for CrsQueue<T> {
     fn default() -> Self {
         let head = Atomic::new(Node::new_empty());
@@ -45,6 +49,9 @@ impl<T> Default for CrsQueue<T> {
             len: AtomicUsize::new(0),
             head,
             tail,
+            free: Atomic::null(),
+            notify: Notify::default(),
+            metrics: Metrics::default(),
         }
     }
 }
@@ -54,20 +61,89 @@ impl<T> CrsQueue<T> {
         Self::default()
     }
 
-    pub fn size(&self) -> usize {
+    /// A new queue in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    pub fn len(&self) -> usize {
         self.len.load(Ordering::SeqCst)
     }
 
+    // kept for compatibility, same as `len`
+    pub fn size(&self) -> usize {
+        self.len()
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.len
-            .compare_exchange(0, 0, Ordering::SeqCst, Ordering::Relaxed)
-            .is_ok()
+        self.len() == 0
+    }
+
+    /// Allocate `n` nodes up front, so the next `n` pushes don't have to.
+    pub fn prewarm(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let guard = &epoch::pin();
+        let first = Owned::new(Node::new_empty()).into_shared(guard);
+        let mut last = first;
+        for _ in 1..n {
+            let node = Owned::new(Node::new_empty()).into_shared(guard);
+            // the chain is not shared yet
+            unsafe { (*last.as_raw()).next.store(node, Ordering::Relaxed) };
+            last = node;
+        }
+        loop {
+            let head = self.free.load(Ordering::Relaxed, guard);
+            unsafe { (*last.as_raw()).next.store(head, Ordering::Relaxed) };
+            if self
+                .free
+                .compare_exchange(head, first, Ordering::Release, Ordering::Relaxed, guard)
+                .is_ok()
+            {
+                break;
+            }
+        }
+    }
+
+    // take a prewarmed node off the free list
+    // nodes never return to the list, and we stay pinned across the CAS,
+    // so the `head` we saw can't be freed and show up again (no ABA)
+    fn take_free<'g>(&self, guard: &'g Guard) -> Option<Shared<'g, Node<T>>> {
+        loop {
+            let head = self.free.load(Ordering::Acquire, guard);
+            if head.is_null() {
+                return None;
+            }
+            let next = unsafe { (*head.as_raw()).next.load(Ordering::Relaxed, guard) };
+            if self
+                .free
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed, guard)
+                .is_ok()
+            {
+                unsafe {
+                    (*head.as_raw())
+                        .next
+                        .store(Shared::null(), Ordering::Relaxed)
+                };
+                return Some(head);
+            }
+        }
     }
 
     pub fn push(&self, data: T) {
         let guard = epoch::pin();
 
-        let new_node = Owned::new(Node::new(data)).into_shared(&guard);
+        let new_node = match self.take_free(&guard) {
+            // ours alone now, a stale `take_free` may only still read `next`
+            Some(node) => unsafe {
+                *(*node.as_raw()).item.get() = Some(data);
+                node
+            },
+            None => Owned::new(Node::new(data)).into_shared(&guard),
+        };
+        // counted before it is linked, so a pop can never uncount it first
+        self.len.fetch_add(1, Ordering::SeqCst);
 
         let old_tail = self.tail.load(Ordering::Acquire, &guard);
         unsafe {
@@ -82,6 +158,7 @@ impl<T> CrsQueue<T> {
                 )
                 .is_err()
             {
+                self.metrics.cas_retry();
                 let mut tail = tail_next.load(Ordering::Acquire, &guard).as_raw();
 
                 // step to tail
@@ -104,37 +181,281 @@ impl<T> CrsQueue<T> {
             &guard,
         );
 
-        self.len.fetch_add(1, Ordering::SeqCst);
+        self.metrics.pushed(1);
+        self.notify.notify_all();
     }
 
     pub fn pop(&self) -> Option<T> {
-        let mut data = None;
         if self.is_empty() {
-            return data;
+            return None;
+        }
+        let data = self.take_in(&epoch::pin())?;
+        let len = self.len.fetch_sub(1, Ordering::SeqCst);
+        debug_assert!(len != 0, "CrsQueue length underflow on pop");
+        self.metrics.popped(1);
+        Some(data)
+    }
+
+    /// Pop up to `max` items into `buf` under a single guard,
+    /// returning how many were popped.
+    ///
+    /// Stops early once the queue is observed empty. Items are appended,
+    /// so a cleared `buf` can be reused across calls without reallocating.
+    pub fn pop_into(&self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 || self.is_empty() {
+            return 0;
         }
         let guard = &epoch::pin();
+        let mut count = 0;
+        while count < max {
+            match self.take_in(guard) {
+                Some(data) => buf.push(data),
+                None => break,
+            }
+            count += 1;
+        }
+        let len = self.len.fetch_sub(count, Ordering::SeqCst);
+        debug_assert!(len >= count, "CrsQueue length underflow on pop");
+        self.metrics.popped(count);
+        count
+    }
+
+    /// A consumer popping under one long-lived guard, see [`CrsConsumer`].
+    pub fn consumer(&self) -> CrsConsumer<'_, T> {
+        CrsConsumer {
+            queue: self,
+            guard: epoch::pin(),
+            pops: 0,
+        }
+    }
+
+    // unlink the front node and take its item, leaving `len` to the caller
+    fn take_in(&self, guard: &Guard) -> Option<T> {
         unsafe {
             loop {
                 let head = self.head.load(Ordering::Acquire, guard);
-                let mut next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
+                let next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
 
                 if next.is_null() {
                     return None;
                 }
 
+                // the casual tail may lag on the node about to be retired,
+                // where the next push would still find it, so move it first
+                let tail = self.tail.load(Ordering::Acquire, guard);
+                if head == tail {
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                        guard,
+                    );
+                    continue;
+                }
+
                 if self
                     .head
                     .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, guard)
                     .is_ok()
                 {
-                    data = next.deref_mut().item.take();
+                    let node = next.as_raw() as *mut Node<T>;
+                    // claim the item, then wait for peekers still reading it
+                    (*node).state.fetch_or(TAKEN, Ordering::AcqRel);
+                    while (*node).state.load(Ordering::Acquire) != TAKEN {
+                        hint::spin_loop();
+                    }
+                    let data = (*(*node).item.get()).take();
                     guard.defer_destroy(head);
+                    return data;
+                }
+                self.metrics.cas_retry();
+            }
+        }
+    }
+
+    /// Run `f` against the front item without removing it.
+    ///
+    /// Under concurrent pops the item may be removed as soon as `peek`
+    /// returns, but the reference stays valid for the whole of `f`:
+    /// the node is protected by the pinned epoch, and a pop claiming
+    /// the item waits for `f` to finish before moving it out.
+    pub fn peek<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let guard = &epoch::pin();
+        unsafe {
+            loop {
+                let head = self.head.load(Ordering::Acquire, guard);
+                let next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
+
+                if next.is_null() {
+                    return None;
+                }
+
+                let state = &(*next.as_raw()).state;
+                let s = state.load(Ordering::Acquire);
+                // already claimed by a pop, which has moved `head` past it
+                if s & TAKEN != 0 {
+                    continue;
+                }
+                if state
+                    .compare_exchange_weak(s, s + READER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let _read = PeekGuard(state);
+                return (*(*next.as_raw()).item.get()).as_ref().map(f);
+            }
+        }
+    }
+
+    /// Operation counters since the queue was created.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> QueueStats {
+        self.metrics.stats()
+    }
+}
+
+/// A queue of reference-counted payloads.
+pub type ArcQueue<T> = CrsQueue<Arc<T>>;
+
+impl<T> ArcQueue<T> {
+    // the `Arc` itself is stored in the node, so there's no extra indirection
+    pub fn push_arc(&self, item: Arc<T>) {
+        self.push(item)
+    }
+
+    pub fn pop_arc(&self) -> Option<Arc<T>> {
+        self.pop()
+    }
+}
+
+/// A queue of boxed closures, run in the order they were spawned.
+///
+/// Tasks must be `Sync` too, as `CrsQueue` is only shareable between
+/// threads when its items are.
+pub type TaskQueue = CrsQueue<Box<dyn FnOnce() + Send + Sync>>;
+
+impl TaskQueue {
+    pub fn spawn(&self, f: impl FnOnce() + Send + Sync + 'static) {
+        self.push(Box::new(f))
+    }
+
+    /// Pop and run one task, returning `false` if there was none.
+    pub fn run_one(&self) -> bool {
+        match self.pop() {
+            Some(task) => {
+                task();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The item returned by [`select_pop`], tagged with the queue it came from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Selected<T, U> {
+    First(T),
+    Second(U),
+}
+
+/// Pop from `a`, or from `b` if `a` is empty, parking the current thread
+/// until a push to either queue when both are empty.
+///
+/// `a` always takes priority when both queues hold items.
+pub fn select_pop<T, U>(a: &CrsQueue<T>, b: &CrsQueue<U>) -> Selected<T, U> {
+    // register before checking, so a push landing between
+    // the checks and `park` still unparks us
+    a.notify.register();
+    b.notify.register();
+    let selected = loop {
+        if let Some(item) = a.pop() {
+            break Selected::First(item);
+        }
+        if let Some(item) = b.pop() {
+            break Selected::Second(item);
+        }
+        thread::park();
+    };
+    a.notify.unregister();
+    b.notify.unregister();
+    selected
+}
+
+impl<T> CrsQueue<T> {
+    // trade lists with an empty queue of the other flavour, returning
+    // how many items were handed over, see `From<CrsQueue<T>> for HeQueue<T>`
+    pub(crate) fn swap_list(&mut self, head: &mut NodePtr<T>, tail: &mut NodePtr<T>) -> usize {
+        let len = self.len();
+        unsafe {
+            let guard = epoch::unprotected();
+            // the casual tail may lag behind the last node
+            let mut last = self.tail.load(Ordering::Relaxed, guard);
+            loop {
+                let next = last.deref().next.load(Ordering::Relaxed, guard);
+                if next.is_null() {
                     break;
                 }
+                last = next;
             }
+            self.tail.store(last, Ordering::Relaxed);
+        }
+        mem::swap(&mut self.head, head);
+        mem::swap(&mut self.tail, tail);
+        self.len.store(0, Ordering::Relaxed);
+        len
+    }
+}
+
+/// Pops from a [`CrsQueue`] without pinning the epoch for every item.
+///
+/// It stays pinned while it lives, and no node retired since it last
+/// pinned can be freed meanwhile, by any thread. It re-pins itself every
+/// [`REPIN_EVERY`](Self::REPIN_EVERY) pops; a consumer left idle, or
+/// popping slowly, must be dropped or [`repin`](Self::repin)ned
+/// regularly, or garbage piles up unbounded.
+pub struct CrsConsumer<'a, T> {
+    queue: &'a CrsQueue<T>,
+    guard: Guard,
+    pops: usize,
+}
+
+impl<T> CrsConsumer<'_, T> {
+    /// Pops between two re-pins.
+    pub const REPIN_EVERY: usize = 128;
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.queue.is_empty() {
+            return None;
         }
-        self.len.fetch_sub(1, Ordering::SeqCst);
-        data
+        let data = self.queue.take_in(&self.guard)?;
+        let len = self.queue.len.fetch_sub(1, Ordering::SeqCst);
+        debug_assert!(len != 0, "CrsQueue length underflow on pop");
+        self.queue.metrics.popped(1);
+
+        self.pops += 1;
+        if self.pops == Self::REPIN_EVERY {
+            self.repin();
+        }
+        Some(data)
+    }
+
+    /// Let the epoch advance past what this consumer may still be reading.
+    pub fn repin(&mut self) {
+        self.pops = 0;
+        self.guard.repin();
+    }
+}
+
+// both flavours share `Node`, so the list is relinked rather than copied
+impl<T> From<HeQueue<T>> for CrsQueue<T> {
+    fn from(mut queue: HeQueue<T>) -> Self {
+        let mut q = CrsQueue::new();
+        let len = queue.swap_list(&mut q.head, &mut q.tail);
+        q.len.store(len, Ordering::Relaxed);
+        q
     }
 }
 
@@ -145,6 +466,14 @@ impl<T> Drop for CrsQueue<T> {
         unsafe {
             let h = self.head.load_consume(guard);
             guard.defer_destroy(h);
+
+            // unused prewarmed nodes were never shared with a reader
+            let mut node = self.free.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let next = (*node.as_raw()).next.load(Ordering::Relaxed, guard);
+                drop(node.into_owned());
+                node = next;
+            }
         }
     }
 }
@@ -168,19 +497,154 @@ impl<T> CrsQueue<T> {
         }
         println!(" size:{} actual: {}", self.size(), actual_len - 1);
     }
+
+    /// Check the list's invariants, describing the first one broken.
+    ///
+    /// Meant for a quiescent queue: the front node is the only one without
+    /// an item, `tail` is on the list, and there are no more nodes than
+    /// counted. The counter may run ahead of the list by pushes still in
+    /// flight, never behind it, so the walk is bounded by it, which also
+    /// catches cycles.
+    #[cfg(test)]
+    pub fn validate(&self) -> Result<(), String> {
+        let guard = &epoch::pin();
+        let len = self.len();
+        let tail = self.tail.load(Ordering::Acquire, guard);
+        let mut node = self.head.load(Ordering::Acquire, guard);
+        let mut nodes = 0;
+        let mut seen_tail = false;
+        unsafe {
+            if (*node.deref().item.get()).is_some() {
+                return Err(String::from("the front node holds an item"));
+            }
+            loop {
+                seen_tail |= node == tail;
+                node = node.deref().next.load(Ordering::Acquire, guard);
+                if node.is_null() {
+                    break;
+                }
+                nodes += 1;
+                if nodes > len {
+                    return Err(format!("more than {} nodes, or a cycle", len));
+                }
+                if (*node.deref().item.get()).is_none() {
+                    return Err(format!("node {} is a second sentinel", nodes));
+                }
+            }
+        }
+        if !seen_tail {
+            return Err(String::from("tail is not reachable from head"));
+        }
+        Ok(())
+    }
+
+    /// Address of the node `head` points at, the sentinel, for debugging.
+    pub fn head_addr(&self) -> usize {
+        let guard = &epoch::pin();
+        self.head.load(Ordering::Acquire, guard).as_raw() as usize
+    }
+
+    /// Address of the node `tail` points at, for debugging.
+    ///
+    /// `tail` is only moved on a best-effort basis, so under concurrent
+    /// pushes it may lag behind the last node.
+    pub fn tail_addr(&self) -> usize {
+        let guard = &epoch::pin();
+        self.tail.load(Ordering::Acquire, guard).as_raw() as usize
+    }
 }
 
 #[cfg(test)]
 mod cq_test {
     use std::{
         sync::{
-            atomic::{AtomicI32, Ordering},
+            atomic::{AtomicI32, AtomicUsize, Ordering},
             Arc, Barrier,
         },
         thread,
     };
 
-    use crate::crs_queue::CrsQueue;
+    use crossbeam_epoch::{self as epoch, Shared};
+
+    use crate::{
+        crs_queue::{select_pop, ArcQueue, CrsQueue, Selected, TaskQueue},
+        he_queue::HeQueue,
+        test_util::{allocations, check_len_bounds},
+    };
+
+    #[test]
+    fn test_shared() {
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        let q = CrsQueue::shared();
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..pad {
+                p.push(i);
+            }
+        });
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let mut got = vec![];
+            while got.len() < pad {
+                if let Some(i) = c.pop() {
+                    got.push(i);
+                }
+            }
+            got
+        });
+        producer.join().unwrap();
+        assert_eq!(consumer.join().unwrap(), (0..pad).collect::<Vec<_>>());
+        assert!(q.is_empty());
+        assert_eq!(Arc::strong_count(&q), 1);
+    }
+
+    #[test]
+    fn test_consumer() {
+        let q = CrsQueue::new();
+        let pad = if cfg!(miri) { 300 } else { 10_000 };
+        for i in 0..pad {
+            q.push(i);
+        }
+        let mut c = q.consumer();
+        for i in 0..pad {
+            assert_eq!(c.pop(), Some(i));
+        }
+        assert_eq!(c.pop(), None);
+        // shares the queue with everything else popping and pushing it
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(c.pop(), Some(4));
+        c.repin();
+        assert_eq!(c.pop(), None);
+        drop(c);
+        assert!(q.is_empty());
+
+        // a consumer on another thread, racing a plain pop
+        let q = Arc::new(CrsQueue::new());
+        for i in 0..pad {
+            q.push(i);
+        }
+        let q1 = q.clone();
+        let t = thread::spawn(move || {
+            let mut c = q1.consumer();
+            let mut got = vec![];
+            while let Some(i) = c.pop() {
+                got.push(i);
+            }
+            got
+        });
+        let mut got = vec![];
+        while let Some(i) = q.pop() {
+            got.push(i);
+        }
+        let mut theirs = t.join().unwrap();
+        assert!(theirs.windows(2).all(|w| w[0] < w[1]));
+        got.append(&mut theirs);
+        got.sort();
+        assert_eq!(got, (0..pad).collect::<Vec<_>>());
+    }
 
     #[test]
     fn test_single() {
@@ -191,12 +655,235 @@ mod cq_test {
         q.push(5);
         q.push(1);
         q.push(4);
+        q.validate().unwrap();
         assert_eq!(q.pop(), Some(1));
         assert_eq!(q.pop(), Some(1));
         assert_eq!(q.pop(), Some(4));
         assert_eq!(q.pop(), Some(5));
         assert_eq!(q.pop(), Some(1));
         assert_eq!(q.pop(), Some(4));
+        q.validate().unwrap();
+    }
+
+    #[test]
+    fn test_size_bounds() {
+        let pad = if cfg!(miri) { 64 } else { 100_000 };
+        check_len_bounds(Arc::new(CrsQueue::new()), 8, pad);
+    }
+
+    #[test]
+    fn test_len() {
+        let q = CrsQueue::new();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.size(), 2);
+        assert!(!q.is_empty());
+        q.pop();
+        q.pop();
+        q.pop();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        q.validate().unwrap();
+    }
+
+    #[test]
+    fn test_pop_into() {
+        let pad = if cfg!(miri) { 100 } else { 100_000 };
+        let batch = 64;
+
+        let q = CrsQueue::new();
+        for i in 0..pad {
+            q.push(i);
+        }
+        assert_eq!(q.pop_into(&mut vec![], 0), 0);
+
+        let mut buf = Vec::with_capacity(batch);
+        let cap = buf.capacity();
+        let mut all = vec![];
+        loop {
+            buf.clear();
+            let n = q.pop_into(&mut buf, batch);
+            assert_eq!(n, buf.len());
+            if n == 0 {
+                break;
+            }
+            all.extend_from_slice(&buf);
+        }
+        // one buffer, never grown
+        assert_eq!(buf.capacity(), cap);
+        assert_eq!(all, (0..pad).collect::<Vec<_>>());
+        assert!(q.is_empty());
+        assert_eq!(q.pop(), None);
+        q.validate().unwrap();
+    }
+
+    #[test]
+    fn test_peek() {
+        let q = CrsQueue::new();
+        assert_eq!(q.peek(|x: &i32| *x), None);
+
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.peek(|x| *x), Some(1));
+        assert_eq!(q.peek(|x| *x), Some(1));
+        assert_eq!(q.size(), 2);
+
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.peek(|x| *x), Some(4));
+        assert_eq!(q.size(), 1);
+        q.validate().unwrap();
+    }
+
+    #[test]
+    fn test_arc() {
+        let payload = Arc::new(String::from("payload"));
+
+        let q = ArcQueue::new();
+        q.push_arc(payload.clone());
+        q.push_arc(payload.clone());
+        assert_eq!(Arc::strong_count(&payload), 3);
+
+        let popped = q.pop_arc().unwrap();
+        assert!(Arc::ptr_eq(&popped, &payload));
+        assert_eq!(Arc::strong_count(&payload), 3);
+        drop(popped);
+        assert_eq!(Arc::strong_count(&payload), 2);
+
+        // the one left behind is released with the queue
+        drop(q);
+        assert_eq!(Arc::strong_count(&payload), 1);
+    }
+
+    #[test]
+    fn test_tasks() {
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let q = TaskQueue::new();
+        for i in 1..=4 {
+            let count = count.clone();
+            q.spawn(move || {
+                count.fetch_add(i, Ordering::SeqCst);
+            });
+        }
+        let log = Arc::new(std::sync::Mutex::new(vec![]));
+        let l = log.clone();
+        q.spawn(move || l.lock().unwrap().push("last"));
+        assert_eq!(q.len(), 5);
+
+        let mut ran = 0;
+        while q.run_one() {
+            ran += 1;
+        }
+        assert_eq!(ran, 5);
+        assert_eq!(count.load(Ordering::SeqCst), 1 + 2 + 3 + 4);
+        assert_eq!(*log.lock().unwrap(), vec!["last"]);
+        assert!(!q.run_one());
+        q.validate().unwrap();
+    }
+
+    #[test]
+    fn test_tasks_shared() {
+        let spawners = 4;
+        let pad = if cfg!(miri) { 16 } else { 1000 };
+
+        let q = Arc::new(TaskQueue::new());
+        let count = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..spawners)
+            .map(|_| {
+                let q = q.clone();
+                let count = count.clone();
+                thread::spawn(move || {
+                    for _ in 0..pad {
+                        let count = count.clone();
+                        q.spawn(move || {
+                            count.fetch_add(1, Ordering::SeqCst);
+                        });
+                    }
+                })
+            })
+            .collect();
+
+        let r = q.clone();
+        let runner = thread::spawn(move || {
+            let mut ran = 0;
+            while ran < spawners * pad {
+                if r.run_one() {
+                    ran += 1;
+                } else {
+                    thread::yield_now();
+                }
+            }
+        });
+        for h in handles {
+            h.join().unwrap();
+        }
+        runner.join().unwrap();
+
+        assert_eq!(count.load(Ordering::SeqCst), spawners * pad);
+        assert!(!q.run_one());
+    }
+
+    #[test]
+    fn test_prewarm() {
+        let n = if cfg!(miri) { 64 } else { 1000 };
+
+        let q = CrsQueue::new();
+        q.prewarm(n);
+        q.prewarm(0);
+        // the first pin registers this thread with the collector, which allocates,
+        // and holding it keeps the pushes' own pins from running the collector,
+        // which may allocate to pass on garbage left by other tests
+        let guard = epoch::pin();
+
+        assert_eq!(allocations(|| (0..n).for_each(|i| q.push(i))), 0);
+        drop(guard);
+        // once the free list runs dry, pushes allocate again
+        assert!(allocations(|| q.push(n)) > 0);
+        assert_eq!(q.len(), n + 1);
+        q.validate().unwrap();
+        for i in 0..=n {
+            assert_eq!(q.pop(), Some(i));
+        }
+        q.validate().unwrap();
+
+        // leftovers are freed with the queue
+        let q = CrsQueue::<String>::new();
+        q.prewarm(8);
+        q.push(String::from("114514"));
+    }
+
+    #[test]
+    fn test_prewarm_concurrent() {
+        let pad = if cfg!(miri) { 200 } else { 10_000 };
+
+        let q = Arc::new(CrsQueue::new());
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        if i % 100 == 0 {
+                            q.prewarm(50);
+                        }
+                        q.push(n * pad + i);
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        q.validate().unwrap();
+        let mut all = vec![];
+        while let Some(i) = q.pop() {
+            all.push(i);
+        }
+        all.sort_unstable();
+        assert_eq!(all, (0..4 * pad).collect::<Vec<_>>());
+        q.validate().unwrap();
     }
 
     #[test]
@@ -223,13 +910,15 @@ mod cq_test {
         });
         // receive after send is finished
         ba3.wait();
+        c.validate().unwrap();
         let mut sum = 0;
         while let Some(got) = c.pop() {
             sum += got;
         }
         let _ = t1.join();
         let _ = t2.join();
-        assert_eq!(sum, (0..(2 * pad)).sum())
+        assert_eq!(sum, (0..(2 * pad)).sum());
+        c.validate().unwrap();
     }
 
     #[test]
@@ -275,6 +964,7 @@ mod cq_test {
         t2.join().unwrap();
         t3.join().unwrap();
         assert_eq!(sum, (0..(3 * pad)).sum());
+        c.validate().unwrap();
     }
 
     #[test]
@@ -336,5 +1026,178 @@ mod cq_test {
         let s = consumer.join().unwrap();
         sum += s;
         assert_eq!(sum, (0..(3 * pad)).sum());
+        c1.validate().unwrap();
+    }
+
+    #[test]
+    fn test_select_priority() {
+        let high = CrsQueue::new();
+        let low = CrsQueue::new();
+        low.push("low 1");
+        high.push(1);
+        low.push("low 2");
+        high.push(2);
+        assert_eq!(select_pop(&high, &low), Selected::First(1));
+        assert_eq!(select_pop(&high, &low), Selected::First(2));
+        assert_eq!(select_pop(&high, &low), Selected::Second("low 1"));
+        assert_eq!(select_pop(&high, &low), Selected::Second("low 2"));
+    }
+
+    #[test]
+    fn test_select_wakeup() {
+        let rounds = 1000;
+
+        let high = Arc::new(CrsQueue::new());
+        let low = Arc::new(CrsQueue::new());
+        let p_high = high.clone();
+        let p_low = low.clone();
+
+        let consumer = thread::spawn(move || {
+            let mut firsts = 0;
+            let mut seconds = 0;
+            for _ in 0..(2 * rounds) {
+                match select_pop(&high, &low) {
+                    Selected::First(_) => firsts += 1,
+                    Selected::Second(_) => seconds += 1,
+                }
+            }
+            (firsts, seconds)
+        });
+
+        // the consumer spends most of its time parked on both queues
+        for i in 0..rounds {
+            p_high.push(i);
+            thread::yield_now();
+            p_low.push(i);
+            thread::yield_now();
+        }
+
+        assert_eq!(consumer.join().unwrap(), (rounds, rounds));
+    }
+
+    #[test]
+    fn test_from_he_queue() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let he = HeQueue::new();
+        he.push_batch(0..pad);
+        he.pop();
+        let q = CrsQueue::from(he);
+        assert_eq!(q.len(), pad - 1);
+        q.validate().unwrap();
+        q.push(pad);
+        for i in 1..=pad {
+            assert_eq!(q.pop(), Some(i));
+        }
+        assert!(q.is_empty());
+        assert_eq!(q.pop(), None);
+
+        // and back again, onto a list a `CrsQueue` has pushed to
+        let q = HeQueue::from(q);
+        q.push(1);
+        assert_eq!(CrsQueue::from(q).pop(), Some(1));
+    }
+
+    #[test]
+    fn test_zst() {
+        let pad = 100_000;
+
+        let q = CrsQueue::new();
+        for _ in 0..pad {
+            q.push(());
+        }
+        assert_eq!(q.size(), pad);
+        for _ in 0..(pad / 2) {
+            assert_eq!(q.pop(), Some(()));
+        }
+        assert_eq!(q.size(), pad / 2);
+        q.validate().unwrap();
+        while q.pop().is_some() {}
+        assert!(q.is_empty());
+        assert_eq!(q.pop(), None);
+        q.validate().unwrap();
+
+        // a zero-sized payload with drop glue, left in the queue on drop
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let q = CrsQueue::new();
+        for _ in 0..pad {
+            q.push(Token);
+        }
+        for _ in 0..(pad / 2) {
+            drop(q.pop());
+        }
+        drop(q);
+        assert_eq!(DROPS.load(Ordering::SeqCst), pad);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_stats() {
+        let q = CrsQueue::new();
+        q.push(1);
+        q.push(4);
+        q.pop();
+        let stats = q.stats();
+        assert_eq!((stats.pushes, stats.pops, stats.cas_retries), (2, 1, 0));
+        // popping an empty queue is not counted
+        q.pop();
+        q.pop();
+        assert_eq!(q.stats().pops, 2);
+    }
+
+    #[test]
+    fn test_addr_emptied() {
+        let q = CrsQueue::new();
+        assert_eq!(q.head_addr(), q.tail_addr());
+        for i in 0..10 {
+            q.push(i);
+        }
+        assert_ne!(q.head_addr(), q.tail_addr());
+        while q.pop().is_some() {}
+        assert_eq!(q.head_addr(), q.tail_addr());
+        q.validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_corrupt() {
+        let q = CrsQueue::new();
+        q.push(1);
+        q.push(4);
+        q.validate().unwrap();
+
+        // the queue is ours alone, everything is put back before it drops
+        unsafe {
+            let guard = epoch::unprotected();
+            let sentinel = q.head.load(Ordering::Relaxed, guard);
+            let first = sentinel.deref().next.load(Ordering::Relaxed, guard);
+            let last = q.tail.load(Ordering::Relaxed, guard);
+
+            // `tail` off the list
+            q.tail.store(Shared::null(), Ordering::Relaxed);
+            assert_eq!(
+                q.validate(),
+                Err(String::from("tail is not reachable from head"))
+            );
+            q.tail.store(last, Ordering::Relaxed);
+
+            // the last node linked back to the first
+            last.deref().next.store(first, Ordering::Relaxed);
+            assert!(q.validate().unwrap_err().contains("cycle"));
+            last.deref().next.store(Shared::null(), Ordering::Relaxed);
+
+            // an item taken without unlinking its node
+            let item = (*first.deref().item.get()).take();
+            assert!(q.validate().unwrap_err().contains("second sentinel"));
+            *first.deref().item.get() = item;
+        }
+        q.validate().unwrap();
+        assert_eq!(q.pop(), Some(1));
     }
 }
@@ -0,0 +1,174 @@
+// a 16 byte word with compare-and-swap: `cmpxchg16b` on x86_64, where the
+// CPU has to be checked for it first, and an exclusive pair loop on aarch64
+//
+// anywhere else, and under miri which can't run the assembly, it is never
+// `supported`, and users keep to their 8 byte fallback
+
+#[cfg(all(not(miri), any(target_arch = "x86_64", target_arch = "aarch64")))]
+use std::arch::asm;
+use std::cell::UnsafeCell;
+
+#[repr(C, align(16))]
+pub(crate) struct AtomicPair {
+    v: UnsafeCell<u128>,
+}
+
+// only ever accessed through the atomic instructions below
+unsafe impl Send for AtomicPair {}
+unsafe impl Sync for AtomicPair {}
+
+impl AtomicPair {
+    pub(crate) fn new(v: u128) -> Self {
+        Self {
+            v: UnsafeCell::new(v),
+        }
+    }
+
+    // whether the CPU has the instruction, every aarch64 one does
+    pub(crate) fn supported() -> bool {
+        #[cfg(all(not(miri), target_arch = "x86_64"))]
+        return std::is_x86_feature_detected!("cmpxchg16b");
+        #[cfg(all(not(miri), target_arch = "aarch64"))]
+        return true;
+        #[cfg(not(all(not(miri), any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        return false;
+    }
+
+    // a 16 byte read is only atomic as part of an RMW, so this swaps the
+    // value with itself
+    pub(crate) fn load(&self) -> u128 {
+        match self.compare_exchange(0, 0) {
+            Ok(v) | Err(v) => v,
+        }
+    }
+
+    // sequentially consistent either way, like any locked instruction
+    pub(crate) fn compare_exchange(&self, current: u128, new: u128) -> Result<u128, u128> {
+        let (prev, ok) = unsafe { cas(self.v.get(), current, new) };
+        if ok {
+            Ok(prev)
+        } else {
+            Err(prev)
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut u128 {
+        self.v.get_mut()
+    }
+}
+
+// SAFETY: `dst` is valid and 16 byte aligned, and the CPU is `supported`
+#[cfg(all(not(miri), target_arch = "x86_64"))]
+unsafe fn cas(dst: *mut u128, old: u128, new: u128) -> (u128, bool) {
+    let (prev_lo, prev_hi): (u64, u64);
+    let ok: u8;
+    // `rbx` is reserved by LLVM, so the low half of `new` goes in through
+    // another register and `rbx` is put back afterwards
+    asm!(
+        "xchg {rbx_tmp}, rbx",
+        "lock cmpxchg16b xmmword ptr [{dst}]",
+        "sete cl",
+        "mov rbx, {rbx_tmp}",
+        rbx_tmp = inout(reg) new as u64 => _,
+        dst = in(reg) dst,
+        in("rcx") (new >> 64) as u64,
+        inout("rax") old as u64 => prev_lo,
+        inout("rdx") (old >> 64) as u64 => prev_hi,
+        lateout("cl") ok,
+        options(nostack),
+    );
+    (prev_lo as u128 | (prev_hi as u128) << 64, ok != 0)
+}
+
+// SAFETY: `dst` is valid and 16 byte aligned
+#[cfg(all(not(miri), target_arch = "aarch64"))]
+unsafe fn cas(dst: *mut u128, old: u128, new: u128) -> (u128, bool) {
+    let (prev_lo, prev_hi): (u64, u64);
+    let failed: u32;
+    // a mismatch still stores back what it read, which is what makes the
+    // read of the pair atomic
+    asm!(
+        "2:",
+        "ldaxp {prev_lo}, {prev_hi}, [{dst}]",
+        "cmp {prev_lo}, {old_lo}",
+        "cset {r:w}, ne",
+        "cmp {prev_hi}, {old_hi}",
+        "cinc {r:w}, {r:w}, ne",
+        "cbz {r:w}, 3f",
+        "stlxp {r:w}, {prev_lo}, {prev_hi}, [{dst}]",
+        "cbnz {r:w}, 2b",
+        "mov {r:w}, #1",
+        "b 4f",
+        "3:",
+        "stlxp {r:w}, {new_lo}, {new_hi}, [{dst}]",
+        "cbnz {r:w}, 2b",
+        "4:",
+        dst = in(reg) dst,
+        old_lo = in(reg) old as u64,
+        old_hi = in(reg) (old >> 64) as u64,
+        new_lo = in(reg) new as u64,
+        new_hi = in(reg) (new >> 64) as u64,
+        prev_lo = out(reg) prev_lo,
+        prev_hi = out(reg) prev_hi,
+        r = out(reg) failed,
+        options(nostack),
+    );
+    (prev_lo as u128 | (prev_hi as u128) << 64, failed == 0)
+}
+
+#[cfg(not(all(not(miri), any(target_arch = "x86_64", target_arch = "aarch64"))))]
+unsafe fn cas(_dst: *mut u128, _old: u128, _new: u128) -> (u128, bool) {
+    unreachable!("16 byte CAS is not supported here")
+}
+
+#[cfg(test)]
+mod dwcas_test {
+    use std::{sync::Arc, thread};
+
+    use crate::dwcas::AtomicPair;
+
+    #[test]
+    fn test_single() {
+        if !AtomicPair::supported() {
+            return;
+        }
+        let big = u128::MAX - 1;
+        let mut p = AtomicPair::new(1);
+        assert_eq!(p.load(), 1);
+        assert_eq!(p.compare_exchange(2, big), Err(1));
+        assert_eq!(p.compare_exchange(1, big), Ok(1));
+        assert_eq!(p.load(), big);
+        // only the high half differs
+        assert_eq!(p.compare_exchange(big as u64 as u128, 0), Err(big));
+        assert_eq!(*p.get_mut(), big);
+    }
+
+    #[test]
+    fn test_concurrent_increments() {
+        if !AtomicPair::supported() {
+            return;
+        }
+        let pad = 10_000u128;
+        // both halves count, and the low one carries into the high one
+        let step = 1 | 1 << 64;
+        let p = Arc::new(AtomicPair::new(u64::MAX as u128 - 2 * pad));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let p = p.clone();
+                thread::spawn(move || {
+                    for _ in 0..pad {
+                        let mut v = p.load();
+                        while let Err(now) = p.compare_exchange(v, v.wrapping_add(step)) {
+                            v = now;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        let start = u64::MAX as u128 - 2 * pad;
+        assert_eq!(p.load(), start.wrapping_add(4 * pad * step));
+    }
+}
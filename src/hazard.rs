@@ -0,0 +1,278 @@
+// hazard pointers: before dereferencing a node, a thread publishes it in a
+// slot, and a retired node is only freed once no slot holds it
+//
+// slots are handed to one operation at a time and reused afterwards, so
+// there are about as many as threads ever operated at once
+
+use std::ptr;
+#[cfg(not(feature = "loom"))]
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// loom has to see every atomic, see `lq`
+#[cfg(feature = "loom")]
+use loom::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// hazards per slot, enough to hold a node and its successor
+pub(crate) const HAZARDS: usize = 2;
+
+// retired nodes piling up before a retire scans the slots
+const SCAN_THRESHOLD: usize = 64;
+
+struct Slot {
+    active: AtomicBool,
+    hazards: [AtomicPtr<u8>; HAZARDS],
+    // slots are never unlinked, so this is fixed once published
+    next: *mut Slot,
+}
+
+struct Retired {
+    ptr: *mut u8,
+    free: unsafe fn(*mut u8),
+    next: *mut Retired,
+}
+
+pub(crate) struct Domain {
+    slots: AtomicPtr<Slot>,
+    retired: AtomicPtr<Retired>,
+    retired_len: AtomicUsize,
+    freed: AtomicUsize,
+}
+
+/// The slot an operation publishes its hazards in, cleared on drop.
+pub(crate) struct Hazards<'a> {
+    slot: &'a Slot,
+}
+
+impl Default for Domain {
+    fn default() -> Self {
+        Self {
+            slots: AtomicPtr::new(ptr::null_mut()),
+            retired: AtomicPtr::new(ptr::null_mut()),
+            retired_len: AtomicUsize::new(0),
+            freed: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Domain {
+    // an idle slot, or a new one if every slot is taken
+    pub(crate) fn hazards(&self) -> Hazards<'_> {
+        let mut p = self.slots.load(Ordering::Acquire);
+        while !p.is_null() {
+            let slot = unsafe { &*p };
+            if !slot.active.load(Ordering::Relaxed)
+                && slot
+                    .active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return Hazards { slot };
+            }
+            p = slot.next;
+        }
+
+        let slot = Box::into_raw(Box::new(Slot {
+            active: AtomicBool::new(true),
+            hazards: Default::default(),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.slots.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*slot).next = head };
+            match self
+                .slots
+                .compare_exchange_weak(head, slot, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    return Hazards {
+                        slot: unsafe { &*slot },
+                    }
+                }
+                Err(h) => head = h,
+            }
+        }
+    }
+
+    /// Hand over `ptr`, already unlinked, to be freed with `free` once no
+    /// slot holds it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must not be reachable by operations starting from now on, and
+    /// must not be retired twice.
+    pub(crate) unsafe fn retire(&self, ptr: *mut u8, free: unsafe fn(*mut u8)) {
+        let node = Box::into_raw(Box::new(Retired {
+            ptr,
+            free,
+            next: ptr::null_mut(),
+        }));
+        self.push_retired(node, node, 1);
+        if self.retired_len.load(Ordering::Relaxed) >= SCAN_THRESHOLD {
+            self.scan();
+        }
+    }
+
+    // frees every retired node no slot holds, and counts them
+    pub(crate) fn scan(&self) -> usize {
+        let mut list = self.retired.swap(ptr::null_mut(), Ordering::Acquire);
+        if list.is_null() {
+            return 0;
+        }
+        // protecting stores and checks with SeqCst, so a hazard published
+        // before its node was unlinked shows up in the reads below
+        fence(Ordering::SeqCst);
+        let hazards = self.hazarded();
+
+        let (mut kept, mut last, mut taken, mut freed) = (ptr::null_mut(), ptr::null_mut(), 0, 0);
+        while !list.is_null() {
+            let node = list;
+            unsafe {
+                list = (*node).next;
+                taken += 1;
+                if hazards.contains(&(*node).ptr) {
+                    (*node).next = kept;
+                    if kept.is_null() {
+                        last = node;
+                    }
+                    kept = node;
+                } else {
+                    let node = Box::from_raw(node);
+                    (node.free)(node.ptr);
+                    freed += 1;
+                }
+            }
+        }
+        self.retired_len.fetch_sub(taken, Ordering::Relaxed);
+        if !kept.is_null() {
+            unsafe { self.push_retired(kept, last, taken - freed) };
+        }
+        self.freed.fetch_add(freed, Ordering::Relaxed);
+        freed
+    }
+
+    // retired nodes freed so far
+    #[cfg(test)]
+    pub(crate) fn freed(&self) -> usize {
+        self.freed.load(Ordering::Relaxed)
+    }
+
+    fn hazarded(&self) -> Vec<*mut u8> {
+        let mut hazards = vec![];
+        let mut p = self.slots.load(Ordering::Acquire);
+        while !p.is_null() {
+            let slot = unsafe { &*p };
+            for h in &slot.hazards {
+                let h = h.load(Ordering::SeqCst);
+                if !h.is_null() {
+                    hazards.push(h);
+                }
+            }
+            p = slot.next;
+        }
+        hazards
+    }
+
+    unsafe fn push_retired(&self, first: *mut Retired, last: *mut Retired, len: usize) {
+        self.retired_len.fetch_add(len, Ordering::Relaxed);
+        let mut top = self.retired.load(Ordering::Relaxed);
+        loop {
+            (*last).next = top;
+            match self.retired.compare_exchange_weak(
+                top,
+                first,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(t) => top = t,
+            }
+        }
+    }
+}
+
+impl Hazards<'_> {
+    /// Publish `ptr` in hazard `i`.
+    ///
+    /// It is only protected once the caller has checked, after this, that
+    /// `ptr` is still reachable.
+    pub(crate) fn protect<T>(&self, i: usize, ptr: *mut T) {
+        self.slot.hazards[i].store(ptr.cast(), Ordering::SeqCst);
+    }
+}
+
+impl Drop for Hazards<'_> {
+    fn drop(&mut self) {
+        for h in &self.slot.hazards {
+            h.store(ptr::null_mut(), Ordering::Release);
+        }
+        self.slot.active.store(false, Ordering::Release);
+    }
+}
+
+impl Drop for Domain {
+    fn drop(&mut self) {
+        // nobody holds a slot any more, so everything retired can go
+        let mut list = self.retired.load(Ordering::Relaxed);
+        while !list.is_null() {
+            let node = unsafe { Box::from_raw(list) };
+            unsafe { (node.free)(node.ptr) };
+            list = node.next;
+        }
+        let mut p = self.slots.load(Ordering::Relaxed);
+        while !p.is_null() {
+            let slot = unsafe { Box::from_raw(p) };
+            p = slot.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod hazard_test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::hazard::Domain;
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn free(ptr: *mut u8) {
+        drop(Box::from_raw(ptr.cast::<u64>()));
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_protected_survives_scan() {
+        let domain = Domain::default();
+        let (a, b) = (Box::into_raw(Box::new(1u64)), Box::into_raw(Box::new(2u64)));
+        let hazards = domain.hazards();
+        hazards.protect(1, a);
+        unsafe {
+            domain.retire(a.cast(), free);
+            domain.retire(b.cast(), free);
+        }
+        assert_eq!(domain.scan(), 1);
+        // still readable while protected
+        assert_eq!(unsafe { *a }, 1);
+
+        // a second operation gets a slot of its own
+        let other = domain.hazards();
+        drop(hazards);
+        assert_eq!(domain.scan(), 1);
+        assert_eq!(domain.scan(), 0);
+        drop(other);
+        assert_eq!(domain.freed(), 2);
+        assert_eq!(FREED.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_drop_frees_retired() {
+        let domain = Domain::default();
+        let hazards = domain.hazards();
+        let a = Box::into_raw(Box::new(3u64));
+        hazards.protect(0, a);
+        unsafe { domain.retire(a.cast(), |p| drop(Box::from_raw(p.cast::<u64>()))) };
+        assert_eq!(domain.scan(), 0);
+        drop(hazards);
+        // left for the drop of the domain
+        drop(domain);
+    }
+}
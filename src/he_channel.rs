@@ -0,0 +1,289 @@
+// an mpsc-shaped channel over `HeQueue`, bounded if the queue is: the last `Sender` to go
+// closes the queue, which tells blocked receivers to stop once it is drained
+
+use std::sync::{Arc, Weak};
+
+use crossbeam_utils::Backoff;
+
+pub use crate::channel::{RecvError, SendError, TryRecvError, TrySendError};
+use crate::he_queue::{HeQueue, PushError};
+
+struct Shared<T> {
+    queue: HeQueue<T>,
+    // every `Sender`/`Receiver` holds a strong count on its side's token
+    senders: Weak<()>,
+    receivers: Weak<()>,
+}
+
+/// The push side of a split [`HeQueue`].
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    token: Option<Arc<()>>,
+}
+
+/// The pop side of a split [`HeQueue`].
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    token: Option<Arc<()>>,
+}
+
+// see `HeQueue::split`, the items already queued stay there
+pub(crate) fn from_queue<T>(queue: HeQueue<T>) -> (Sender<T>, Receiver<T>) {
+    let senders = Arc::new(());
+    let receivers = Arc::new(());
+    let shared = Arc::new(Shared {
+        queue,
+        senders: Arc::downgrade(&senders),
+        receivers: Arc::downgrade(&receivers),
+    });
+    let tx = Sender {
+        shared: shared.clone(),
+        token: Some(senders),
+    };
+    let rx = Receiver {
+        shared,
+        token: Some(receivers),
+    };
+    (tx, rx)
+}
+
+impl<T> Sender<T> {
+    /// Push `item`, or hand it back once every `Receiver` is gone.
+    ///
+    /// On a queue built with a capacity this waits while it is full,
+    /// spinning and then yielding the thread between tries, as nothing
+    /// signals a pop to a waiting sender.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let backoff = Backoff::new();
+        let mut item = item;
+        loop {
+            match self.try_send(item) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(item)) => return Err(SendError(item)),
+                Err(TrySendError::Full(back)) => item = back,
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Push `item` unless the queue is at capacity or every `Receiver`
+    /// is gone, handing it back either way.
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        if self.shared.receivers.strong_count() == 0 {
+            return Err(TrySendError::Disconnected(item));
+        }
+        self.shared.queue.try_push(item).map_err(|e| match e {
+            PushError::Full(item) => TrySendError::Full(item),
+            // only if the queue was closed before being split
+            PushError::Closed(item) => TrySendError::Disconnected(item),
+        })
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Pop an item, parking the thread while the queue is empty.
+    ///
+    /// Once every `Sender` is gone, the items still queued are handed
+    /// out first, then this returns [`RecvError::Disconnected`].
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.shared
+            .queue
+            .pop_until_closed()
+            .ok_or(RecvError::Disconnected)
+    }
+
+    /// Pop an item if one is queued, without waiting.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let queue = &self.shared.queue;
+        if let Some(item) = queue.pop() {
+            return Ok(item);
+        }
+        if !queue.is_finished() {
+            return Err(TryRecvError::Empty);
+        }
+        // a send may have landed between the pop and the last sender's
+        // close, and nothing gets in once finished, so one more pop settles it
+        queue.pop().ok_or(TryRecvError::Disconnected)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            token: self.token.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            token: self.token.clone(),
+        }
+    }
+}
+
+// closing wakes the parked receivers, and any sender still racing with
+// the last drop has its item refused rather than stranded
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        drop(self.token.take());
+        if self.shared.senders.strong_count() == 0 {
+            self.shared.queue.close();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        drop(self.token.take());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::{RecvError, SendError, TryRecvError, TrySendError};
+    use crate::{he_queue::HeQueue, test_util::check_logs};
+
+    #[test]
+    fn test_single() {
+        let q: HeQueue<_> = [1, 1].into_iter().collect();
+        let (tx, rx) = q.split();
+        for i in [4, 5, 1, 4] {
+            tx.send(i).unwrap();
+        }
+        for i in [1, 1, 4, 5, 1, 4] {
+            assert_eq!(rx.recv(), Ok(i));
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_senders_gone() {
+        let (tx, rx) = HeQueue::new().split();
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        tx2.send(2).unwrap();
+
+        // a parked receiver drains what's left once the last sender goes
+        let t = thread::spawn(move || {
+            let mut got = vec![];
+            while let Ok(i) = rx.recv() {
+                got.push(i);
+            }
+            got
+        });
+        thread::sleep(Duration::from_millis(100));
+        drop(tx2);
+        assert_eq!(t.join().unwrap(), vec![1, 2]);
+
+        let (tx, rx) = HeQueue::<i32>::new().split();
+        drop(tx);
+        assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_try_recv() {
+        let (tx, rx) = HeQueue::new().split();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.send(1).unwrap();
+        tx.send(4).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+
+        let tx2 = tx.clone();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(4));
+        // a sender is still around
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx2.send(5).unwrap();
+        drop(tx2);
+        assert_eq!(rx.try_recv(), Ok(5));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_receivers_gone() {
+        let (tx, rx) = HeQueue::new().split();
+        let rx2 = rx.clone();
+        drop(rx);
+        assert_eq!(tx.send(1), Ok(()));
+        drop(rx2);
+        assert_eq!(tx.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_full() {
+        let (tx, rx) = HeQueue::with_capacity(2).split();
+        tx.send(1).unwrap();
+        assert_eq!(tx.try_send(4), Ok(()));
+        // full is not gone
+        assert_eq!(tx.try_send(5), Err(TrySendError::Full(5)));
+
+        // a blocked send goes through once a receiver makes room
+        let t = {
+            let tx = tx.clone();
+            thread::spawn(move || tx.send(5))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(t.join().unwrap(), Ok(()));
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert_eq!(rx.try_recv(), Ok(5));
+
+        // or gives up once the receivers are gone
+        tx.send(1).unwrap();
+        tx.send(4).unwrap();
+        let t = {
+            let tx = tx.clone();
+            thread::spawn(move || tx.send(5))
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        assert_eq!(t.join().unwrap(), Err(SendError(5)));
+        assert_eq!(tx.try_send(5), Err(TrySendError::Disconnected(5)));
+    }
+
+    #[test]
+    fn test_mpmc() {
+        let producers = 3;
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        let (tx, rx) = HeQueue::new().split();
+        let pushed: Vec<_> = (0..producers)
+            .map(|n| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        tx.send((n, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let popped: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || {
+                    let mut log = vec![];
+                    while let Ok(item) = rx.recv() {
+                        log.push(item);
+                    }
+                    log
+                })
+            })
+            .collect();
+        drop(rx);
+
+        for p in pushed {
+            p.join().unwrap();
+        }
+        let logs: Vec<_> = popped.into_iter().map(|c| c.join().unwrap()).collect();
+        check_logs(producers, pad, &logs);
+    }
+}
@@ -1,51 +1,218 @@
 // based on crossbeam
 // push with strict tail algorithm
 
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::AtomicUsize;
+use core::{fmt, mem, ops::Deref};
+#[cfg(not(feature = "loom"))]
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "async")]
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(feature = "std")]
 use std::{
-    io::Write,
-    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_epoch as epoch;
+use crossbeam_utils::{Backoff, CachePadded};
+#[cfg(not(feature = "std"))]
+use epoch::Collector;
+use epoch::{Atomic, Guard, Owned, Shared};
+#[cfg(feature = "async")]
+use futures_core::Stream;
+// loom has to see every atomic, and spinning must yield to its scheduler
+#[cfg(feature = "loom")]
+use loom::sync::atomic::AtomicUsize;
+#[cfg(feature = "loom")]
+use loom::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread::yield_now as spin_loop,
 };
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
 
-use crossbeam::epoch;
-use epoch::{Atomic, Owned, Shared};
+#[cfg(feature = "metrics")]
+use crate::metrics::QueueStats;
+#[cfg(feature = "async")]
+use crate::waker_list::{WakerList, WakerSlot};
+#[cfg(feature = "std")]
+use crate::{
+    crs_queue::CrsQueue,
+    he_channel::{self, Receiver, Sender},
+};
+use crate::{
+    metrics::{HeCounters, Metrics},
+    node::{Node, NodePtr, PeekGuard, READER, TAKEN},
+    notify::Notify,
+};
 
-type NodePtr<T> = Atomic<Node<T>>;
-struct Node<T> {
-    pub item: Option<T>,
-    pub next: NodePtr<T>,
+/// How `HeQueue` waits before retrying a failed CAS.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackoffPolicy {
+    /// Spin with exponentially growing pauses.
+    Spin,
+    /// Spin at first, then start yielding the thread.
+    #[default]
+    SpinThenYield,
 }
 
-impl<T> Node<T> {
-    pub fn new_empty() -> Self {
-        Self {
-            item: None,
-            next: Atomic::null(),
+impl BackoffPolicy {
+    fn backoff(self, backoff: &Backoff) {
+        match self {
+            BackoffPolicy::Spin => backoff.spin(),
+            BackoffPolicy::SpinThenYield => backoff.snooze(),
+        }
+    }
+}
+
+/// The memory orderings `HeQueue` uses where correctness leaves a choice,
+/// for measuring what the stronger ones cost.
+///
+/// The orderings publishing nodes and items are the same in both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderingProfile {
+    /// Acquire `len` reads, sequentially consistent `closed` accesses and
+    /// acquire-release link CASes.
+    #[default]
+    Strong,
+    /// Relaxed `len` reads, acquire/release `closed` accesses and
+    /// release-only link CASes.
+    Relaxed,
+}
+
+impl OrderingProfile {
+    // `len` is approximate either way, the acquire only orders later
+    // reads after the counter's updates
+    fn len_load(self) -> Ordering {
+        match self {
+            OrderingProfile::Strong => Ordering::Acquire,
+            OrderingProfile::Relaxed => Ordering::Relaxed,
         }
     }
 
-    pub fn new(data: T) -> Self {
-        Self {
-            item: Some(data),
-            next: Atomic::null(),
+    // an item pushed before `close` must be visible to a consumer that
+    // sees the flag, and parking pairs through `Notify`'s fences
+    fn closed_load(self) -> Ordering {
+        match self {
+            OrderingProfile::Strong => Ordering::SeqCst,
+            OrderingProfile::Relaxed => Ordering::Acquire,
+        }
+    }
+
+    fn closed_store(self) -> Ordering {
+        match self {
+            OrderingProfile::Strong => Ordering::SeqCst,
+            OrderingProfile::Relaxed => Ordering::Release,
+        }
+    }
+
+    // a successful link or tail help only publishes, what it replaced
+    // (null, or a tail already read with acquire) carries nothing to acquire
+    fn link_success(self) -> Ordering {
+        match self {
+            OrderingProfile::Strong => Ordering::AcqRel,
+            OrderingProfile::Relaxed => Ordering::Release,
         }
     }
 }
 
+// each hot field gets a cache line of its own, so consumers working on
+// `head` and producers working on `tail` don't false-share
 pub struct HeQueue<T> {
-    len: AtomicUsize,
-    head: NodePtr<T>,
-    tail: NodePtr<T>,
+    // consumer side
+    head: CachePadded<NodePtr<T>>,
+    // producer side
+    tail: CachePadded<NodePtr<T>>,
+    // touched by both, compiled out by the `he-no-len` feature
+    #[cfg(not(feature = "he-no-len"))]
+    len: CachePadded<AtomicUsize>,
+    // with `he-no-len`, only a bounded queue keeps a count, so that
+    // `try_push` doesn't walk the whole list for every item
+    #[cfg(feature = "he-no-len")]
+    bounded_len: Option<CachePadded<AtomicUsize>>,
+    backoff: BackoffPolicy,
+    orderings: OrderingProfile,
+    // only enforced by `try_push`
+    capacity: Option<usize>,
+    closed: AtomicBool,
+    // pushes past their `closed` check that haven't linked yet,
+    // the end is only reported once there are none
+    pushing: CachePadded<AtomicUsize>,
+    metrics: Metrics,
+    // compiled out unless the `stats` feature is on
+    counters: HeCounters,
+    // threads parked in `pop_blocking` and `pop_deadline`,
+    // zero sized without `std`
+    waiters: Notify,
+    // without `std` there is no global collector to pin
+    #[cfg(not(feature = "std"))]
+    collector: Collector,
+    // tasks waiting in `HeStream::poll_next`
+    #[cfg(feature = "async")]
+    wakers: WakerList,
+}
+
+/// Returned by the bounded queues' `try_push` when the item can't be queued,
+/// handing it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushError<T> {
+    /// The queue is at capacity.
+    Full(T),
+    /// The queue has been closed.
+    Closed(T),
+}
+
+impl<T> PushError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            PushError::Full(data) | PushError::Closed(data) => data,
+        }
+    }
+}
+
+/// Why [`HeQueue::pop_result`] came back without an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopError {
+    /// Nothing queued right now, more may come.
+    Empty,
+    /// Closed and drained, nothing more will come.
+    Closed,
+}
+
+// the item slots are only reached through the TAKEN/READER protocol,
+// and `peek_with` hands out `&T` to any thread, hence `T: Sync`
+unsafe impl<T: Send> Send for HeQueue<T> {}
+unsafe impl<T: Send + Sync> Sync for HeQueue<T> {}
+
+// a push counted in `HeQueue::pushing`
+struct PushToken<'a, T>(&'a HeQueue<T>);
+
+impl<T> Drop for PushToken<'_, T> {
+    fn drop(&mut self) {
+        let queue = self.0;
+        // consumers waiting for the end may have seen us counted and
+        // parked, and a refused push links nothing to wake them with
+        if queue.pushing.fetch_sub(1, Ordering::SeqCst) == 1 && queue.is_closed() {
+            queue.waiters.notify_all();
+            #[cfg(feature = "async")]
+            queue.wakers.wake_all();
+        }
+    }
 }
 
 impl<T> Default for HeQueue<T> {
     fn default() -> Self {
-        let head = Atomic::new(Node::new_empty());
-        let tail = head.clone();
-        Self {
-            len: AtomicUsize::new(0),
-            head,
-            tail,
-        }
+        Self::with_backoff(BackoffPolicy::default())
     }
 }
 
@@ -54,280 +221,4275 @@ impl<T> HeQueue<T> {
         Self::default()
     }
 
+    /// A new queue in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    pub fn with_backoff(backoff: BackoffPolicy) -> Self {
+        Self::build(backoff, OrderingProfile::default(), None)
+    }
+
+    pub fn with_orderings(orderings: OrderingProfile) -> Self {
+        Self::build(BackoffPolicy::default(), orderings, None)
+    }
+
+    /// A queue using [`OrderingProfile::Relaxed`].
+    pub fn new_relaxed() -> Self {
+        Self::with_orderings(OrderingProfile::Relaxed)
+    }
+
+    /// A queue whose `try_push` rejects items once `capacity` are queued.
+    ///
+    /// The bound is advisory: pushers racing past the length check may
+    /// overshoot it by up to one item each, and `push` ignores it.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::build(
+            BackoffPolicy::default(),
+            OrderingProfile::default(),
+            Some(capacity),
+        )
+    }
+
+    fn build(backoff: BackoffPolicy, orderings: OrderingProfile, capacity: Option<usize>) -> Self {
+        let head = Atomic::new(Node::new_empty());
+        let tail = head.clone();
+        Self {
+            head: CachePadded::new(head),
+            tail: CachePadded::new(tail),
+            #[cfg(not(feature = "he-no-len"))]
+            len: CachePadded::new(AtomicUsize::new(0)),
+            #[cfg(feature = "he-no-len")]
+            bounded_len: capacity.map(|_| CachePadded::new(AtomicUsize::new(0))),
+            backoff,
+            orderings,
+            capacity,
+            closed: AtomicBool::new(false),
+            pushing: CachePadded::new(AtomicUsize::new(0)),
+            metrics: Metrics::default(),
+            counters: HeCounters::default(),
+            waiters: Notify::default(),
+            #[cfg(not(feature = "std"))]
+            collector: Collector::new(),
+            #[cfg(feature = "async")]
+            wakers: WakerList::default(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn pin(&self) -> Guard {
+        epoch::pin()
+    }
+
+    // without thread locals every call registers anew, an allocation
+    // per operation, which `push_with`/`pop_with` avoid
+    #[cfg(not(feature = "std"))]
+    fn pin(&self) -> Guard {
+        self.collector.register().pin()
+    }
+
+    // our nodes are retired through the global collector, a guard from
+    // any other one doesn't keep them alive while we read them
+    #[cfg(feature = "std")]
+    fn check_guard(&self, guard: &Guard) {
+        assert!(
+            guard.collector() == Some(epoch::default_collector()),
+            "guard not pinned on the global collector"
+        );
+    }
+
+    // likewise, only our own collector keeps our nodes alive
+    #[cfg(not(feature = "std"))]
+    fn check_guard(&self, guard: &Guard) {
+        assert!(
+            guard.collector() == Some(&self.collector),
+            "guard not pinned on the queue's collector"
+        );
+    }
+
+    /// The collector to register with for the guards taken by
+    /// [`HeQueue::push_with`] and [`HeQueue::pop_with`].
+    ///
+    /// Without `std` there is no global collector, so every queue has
+    /// its own, and passing them a guard from any other panics.
+    #[cfg(not(feature = "std"))]
+    pub fn collector(&self) -> &Collector {
+        &self.collector
+    }
+
+    /// Number of items in the queue.
+    ///
+    /// Items are counted just before they are linked and uncounted just
+    /// after they are unlinked, so while other threads push or pop this
+    /// may run ahead of what `pop` can see, but it is exact once they
+    /// have all finished.
+    #[cfg(not(feature = "he-no-len"))]
+    pub fn len(&self) -> usize {
+        self.len.load(self.orderings.len_load())
+    }
+
+    /// The number of queued items, counted by walking the whole list,
+    /// as `he-no-len` leaves no counter to read.
+    ///
+    /// A queue built [`with_capacity`](Self::with_capacity) still keeps
+    /// one, which is read instead, as approximate as without `he-no-len`.
+    #[cfg(feature = "he-no-len")]
+    pub fn len(&self) -> usize {
+        match &self.bounded_len {
+            Some(len) => len.load(self.orderings.len_load()),
+            None => self.count_nodes(),
+        }
+    }
+
+    #[cfg(not(feature = "he-no-len"))]
+    fn count_add(&self, n: usize) {
+        self.len.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[cfg(not(feature = "he-no-len"))]
+    fn count_sub(&self, n: usize) {
+        // every unlinked item was counted before it was linked,
+        // so the counter can only go below zero through a bug
+        let len = self.len.fetch_sub(n, Ordering::Relaxed);
+        debug_assert!(
+            len >= n,
+            "HeQueue length underflow: uncounting {n} of {len} items"
+        );
+    }
+
+    #[cfg(feature = "he-no-len")]
+    fn count_add(&self, n: usize) {
+        if let Some(len) = &self.bounded_len {
+            len.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "he-no-len")]
+    fn count_sub(&self, n: usize) {
+        if let Some(len) = &self.bounded_len {
+            let len = len.fetch_sub(n, Ordering::Relaxed);
+            debug_assert!(
+                len >= n,
+                "HeQueue length underflow: uncounting {n} of {len} items"
+            );
+        }
+    }
+
+    // kept for compatibility, same as `len`
     pub fn size(&self) -> usize {
-        self.len.load(Ordering::SeqCst)
+        self.len()
+    }
+
+    /// The bound given to [`with_capacity`](Self::with_capacity), if any.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// How many more items `try_push` should take, `None` if unbounded.
+    ///
+    /// A racy estimate built on `len`, already stale when it returns.
+    pub fn remaining_capacity(&self) -> Option<usize> {
+        self.capacity.map(|c| c.saturating_sub(self.len()))
+    }
+
+    // as approximate as `len`, `pop` never trusts it
+    #[cfg(not(feature = "he-no-len"))]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
+    // without a counter, look at the list itself
+    #[cfg(feature = "he-no-len")]
     pub fn is_empty(&self) -> bool {
-        0 == self.len.load(Ordering::SeqCst)
+        self.is_structurally_empty()
+    }
+
+    /// Whether the list itself holds no items, ignoring the length counter.
+    ///
+    /// `is_empty` reads the counter, which counts an item just before it
+    /// is linked and uncounts it just after it is unlinked, so mid-flight
+    /// `is_empty` may say `false` while the list holds nothing poppable
+    /// yet. Once the queue is quiescent they agree.
+    pub fn is_structurally_empty(&self) -> bool {
+        let guard = &self.pin();
+        unsafe {
+            let head = self.head.load(Ordering::Acquire, guard);
+            head.deref().next.load(Ordering::Acquire, guard).is_null()
+        }
+    }
+
+    /// Mark the queue closed, telling consumers no more items are coming.
+    ///
+    /// Items already queued can still be popped, and streams and
+    /// `pop_result` report the end once they are gone. Later pushes are
+    /// refused, though one already past its check when `close` runs
+    /// still lands, and the end is only reported after it has.
+    pub fn close(&self) {
+        self.closed.store(true, self.orderings.closed_store());
+        // parked consumers that stop on close re-check and leave,
+        // `pop_blocking` ones just go back to sleep
+        self.waiters.notify_all();
+        #[cfg(feature = "async")]
+        self.wakers.wake_all();
     }
 
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(self.orderings.closed_load())
+    }
+
+    // count a push in flight until the token is dropped, or refuse it
+    // with `None` if the queue is closed
+    fn start_push(&self) -> Option<PushToken<'_, T>> {
+        // SeqCst with the loads in `is_finished`, so a consumer that sees
+        // the flag we missed also sees us counted, whatever the profile
+        self.pushing.fetch_add(1, Ordering::SeqCst);
+        let token = PushToken(self);
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(token)
+    }
+
+    // closed, and every push that got past its check has linked its
+    // items, so a `pop` that comes back empty after this is final
+    pub(crate) fn is_finished(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) && self.pushing.load(Ordering::SeqCst) == 0
+    }
+
+    /// Push `data` to the back, or drop it if the queue is closed,
+    /// see [`HeQueue::try_push`] to get it back instead.
     pub fn push(&self, data: T) {
-        let guard = epoch::pin();
+        self.push_with(data, &self.pin())
+    }
+
+    /// Push under a guard the caller pinned, see [`HeQueue::pop_with`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` was not pinned on the global collector,
+    /// or without `std`, on the queue's `collector()`.
+    pub fn push_with(&self, data: T, guard: &Guard) {
+        self.check_guard(guard);
+        let Some(_token) = self.start_push() else {
+            return;
+        };
+        self.link(data, guard);
+    }
+
+    // push without looking at `closed` or `capacity`
+    fn link(&self, data: T, guard: &Guard) {
+        let new_node = Owned::new(Node::new(data)).into_shared(guard);
+        self.count_add(1);
+        self.append_chain(new_node, new_node, guard);
+
+        self.metrics.pushed(1);
+        self.waiters.notify_one();
+    }
+
+    /// Push `data` unless the queue is closed or at capacity,
+    /// in which case it is handed back.
+    #[must_use = "a refused item is handed back in the error, and dropped if ignored"]
+    pub fn try_push(&self, data: T) -> Result<(), PushError<T>> {
+        let Some(_token) = self.start_push() else {
+            return Err(PushError::Closed(data));
+        };
+        match self.capacity {
+            Some(capacity) if self.len() >= capacity => Err(PushError::Full(data)),
+            _ => {
+                self.link(data, &self.pin());
+                Ok(())
+            }
+        }
+    }
+
+    /// Push every item of `iter` with a single epoch pin.
+    ///
+    /// The items are linked into a private chain first, which is then
+    /// appended as a whole, so other producers never interleave with it.
+    /// Like `push`, a closed queue drops them.
+    pub fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        let Some(_token) = self.start_push() else {
+            return;
+        };
+        let guard = self.pin();
+
+        let mut iter = iter.into_iter();
+        let first = match iter.next() {
+            Some(data) => Owned::new(Node::new(data)).into_shared(&guard),
+            None => return,
+        };
+        let mut last = first;
+        let mut count = 1;
+        for data in iter {
+            let node = Owned::new(Node::new(data)).into_shared(&guard);
+            // the chain is not shared yet, nobody else can see these stores
+            unsafe {
+                (*last.as_raw()).next.store(node, Ordering::Relaxed);
+            }
+            last = node;
+            count += 1;
+        }
+        self.count_add(count);
+        self.append_chain(first, last, &guard);
+
+        self.metrics.pushed(count);
+        if count == 1 {
+            self.waiters.notify_one();
+        } else {
+            self.waiters.notify_all();
+        }
+    }
+
+    /// Move every item of `other` to the back of this queue in a single splice.
+    ///
+    /// `other` is taken by value, so its nodes are relinked rather than
+    /// popped and pushed again, and producers never interleave with them.
+    /// Like `push`, a closed queue drops them.
+    pub fn append(&self, other: HeQueue<T>) {
+        let Some(_token) = self.start_push() else {
+            return;
+        };
+        #[cfg(not(feature = "he-no-len"))]
+        let count = other.len();
+        let guard = &self.pin();
+        // `other` is ours alone, its nodes only become shared in `append_chain`
+        let (first, last, count) = unsafe {
+            let sentinel = other.head.load(Ordering::Relaxed, guard);
+            let first = (*sentinel.as_raw()).next.load(Ordering::Relaxed, guard);
+            if first.is_null() {
+                return;
+            }
+            // `tail` may lag behind the last node, see `append_chain`
+            #[cfg(not(feature = "he-no-len"))]
+            let mut last = other.tail.load(Ordering::Relaxed, guard);
+            // with no counter to read, count the nodes on the way to the
+            // last one rather than walk them twice
+            #[cfg(feature = "he-no-len")]
+            let (mut last, mut count) = (first, 1);
+            loop {
+                let next = (*last.as_raw()).next.load(Ordering::Relaxed, guard);
+                if next.is_null() {
+                    break;
+                }
+                last = next;
+                #[cfg(feature = "he-no-len")]
+                {
+                    count += 1;
+                }
+            }
+
+            // leave `other` an empty queue, whose drop retires the sentinel
+            (*sentinel.as_raw())
+                .next
+                .store(Shared::null(), Ordering::Relaxed);
+            other.tail.store(sentinel, Ordering::Relaxed);
+            (first, last, count)
+        };
+        drop(other);
+        self.count_add(count);
+        self.append_chain(first, last, guard);
+
+        self.metrics.pushed(count);
+        self.waiters.notify_all();
+    }
 
-        let new_node = Owned::new(Node::new(data)).into_shared(&guard);
+    /// Push a copy of every item in `items`, as one batch.
+    pub fn extend_from_slice(&self, items: &[T])
+    where
+        T: Copy,
+    {
+        self.push_batch(items.iter().copied());
+    }
 
+    // link the chain `first..=last` after the current tail, then swing `tail` to `last`
+    fn append_chain<'g>(
+        &self,
+        first: Shared<'g, Node<T>>,
+        last: Shared<'g, Node<T>>,
+        guard: &'g Guard,
+    ) {
+        let backoff = Backoff::new();
         let mut tail;
         unsafe {
             let null = Shared::null();
             loop {
-                tail = self.tail.load(Ordering::Acquire, &guard);
+                tail = self.tail.load(Ordering::Acquire, guard);
                 let tail_next = &(*tail.as_raw()).next;
+                self.counters.push_attempt();
                 if tail_next
-                    .compare_exchange(null, new_node, Ordering::AcqRel, Ordering::Relaxed, &guard)
+                    .compare_exchange(
+                        null,
+                        first,
+                        self.orderings.link_success(),
+                        Ordering::Relaxed,
+                        guard,
+                    )
                     .is_ok()
                 {
                     break;
                 }
-                let tail_next = tail_next.load(Ordering::Acquire, &guard);
-                let _ = self.tail.compare_exchange(
-                    tail,
-                    tail_next,
-                    Ordering::AcqRel,
-                    Ordering::Relaxed,
-                    &guard,
-                );
+                self.metrics.cas_retry();
+                self.counters.push_cas_failure();
+                self.backoff.backoff(&backoff);
+                let tail_next = tail_next.load(Ordering::Acquire, guard);
+                if self
+                    .tail
+                    .compare_exchange(
+                        tail,
+                        tail_next,
+                        self.orderings.link_success(),
+                        Ordering::Relaxed,
+                        guard,
+                    )
+                    .is_ok()
+                {
+                    self.counters.tail_help();
+                }
             }
         }
-        let _ = self.tail.compare_exchange(
-            tail,
-            new_node,
-            Ordering::Release,
-            Ordering::Relaxed,
-            &guard,
-        );
-
-        self.len.fetch_add(1, Ordering::SeqCst);
+        let _ = self
+            .tail
+            .compare_exchange(tail, last, Ordering::Release, Ordering::Relaxed, guard);
+        #[cfg(feature = "async")]
+        self.wakers.wake_all();
     }
 
+    /// Pop the front item, or `None` if the queue is observed empty.
+    ///
+    /// There is no `is_empty` pre-check to skip: the list itself is the
+    /// only source of truth, so a non-empty queue goes straight to the CAS.
     pub fn pop(&self) -> Option<T> {
-        let mut data = None;
-        if self.is_empty() {
-            return data;
+        self.pop_with(&self.pin())
+    }
+
+    /// Pop under a guard the caller pinned with `crossbeam_epoch::pin`,
+    /// saving a pin per operation when doing many in a row.
+    ///
+    /// Nodes retired while any guard is held can't be freed until it is
+    /// dropped or repinned, so memory grows with the number of operations
+    /// done under one pin. Call `Guard::repin` every hundred or so
+    /// operations, as [`HeQueue::drain`] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` was not pinned on the global collector,
+    /// or without `std`, on the queue's `collector()`.
+    pub fn pop_with(&self, guard: &Guard) -> Option<T> {
+        self.check_guard(guard);
+        // emptiness is decided by `head.next` being null, not by the counter,
+        // so a stale counter cannot hide an item
+        let data = self.take_in(guard);
+        if data.is_some() {
+            self.count_sub(1);
+            self.metrics.popped(1);
         }
-        let guard = &epoch::pin();
+        data
+    }
+
+    /// Pop the front item, telling an empty queue that may still get
+    /// more apart from one that is closed and drained.
+    ///
+    /// Every item whose push was accepted, even one racing with `close`,
+    /// is returned before [`PopError::Closed`].
+    pub fn pop_result(&self) -> Result<T, PopError> {
+        if let Some(data) = self.pop() {
+            return Ok(data);
+        }
+        if !self.is_finished() {
+            return Err(PopError::Empty);
+        }
+        // an accepted item may have landed since the first try
+        self.pop().ok_or(PopError::Closed)
+    }
+
+    // unlink the front node and take its item, leaving `len` to the caller
+    fn take_in(&self, guard: &Guard) -> Option<T> {
+        let backoff = Backoff::new();
         unsafe {
             loop {
                 let head = self.head.load(Ordering::Acquire, guard);
-                let mut next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
+                let next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
 
                 if next.is_null() {
                     return None;
                 }
 
+                // `tail` lags behind on the node about to be retired,
+                // help it forward before swinging `head` past it
+                let tail = self.tail.load(Ordering::Acquire, guard);
+                if head == tail {
+                    if self
+                        .tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed, guard)
+                        .is_ok()
+                    {
+                        self.counters.tail_help();
+                    }
+                    continue;
+                }
+
                 if self
                     .head
                     .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, guard)
                     .is_ok()
                 {
-                    data = next.deref_mut().item.take();
-                    guard.defer_destroy(head);
-                    break;
+                    return self.claim(head, next, guard);
                 }
+                self.metrics.cas_retry();
+                self.counters.pop_retry();
+                self.backoff.backoff(&backoff);
             }
         }
-        self.len.fetch_sub(1, Ordering::SeqCst);
-        data
     }
-}
 
-impl<T> Drop for HeQueue<T> {
-    fn drop(&mut self) {
-        while self.pop().is_some() {}
-        let guard = &epoch::pin();
-        unsafe {
-            let h = self.head.load_consume(guard);
-            guard.defer_destroy(h);
+    // take the item out of `next` once `head` has been swung from `head` to it,
+    // and retire the old sentinel
+    unsafe fn claim(
+        &self,
+        head: Shared<'_, Node<T>>,
+        next: Shared<'_, Node<T>>,
+        guard: &Guard,
+    ) -> Option<T> {
+        let node = &*next.as_raw();
+        // claim the item, then wait for peekers still reading it
+        node.state.fetch_or(TAKEN, Ordering::AcqRel);
+        while node.state.load(Ordering::Acquire) != TAKEN {
+            spin_loop();
         }
+        let data = (*node.item.get()).take();
+        self.retire(head, guard);
+        data
     }
-}
 
-impl<T> HeQueue<T> {
-    // used for debugging
-    // walk through the queue and print each element's address
-    pub fn walk(&self) {
-        let _ = std::io::stdout().flush();
-        let guard = epoch::pin();
-        let mut start = self.head.load(Ordering::Acquire, &guard);
-
-        let mut actual_len = 0;
-        while !start.is_null() {
-            unsafe {
-                print!("-> {:?}", start.as_raw());
-                let _ = std::io::stdout().flush();
-                start = (*start.as_raw()).next.load(Ordering::Acquire, &guard);
-            }
-            actual_len += 1;
+    // hand an unlinked node to the collector, with `stats` keeping
+    // count of it until it is actually freed
+    unsafe fn retire(&self, node: Shared<'_, Node<T>>, guard: &Guard) {
+        #[cfg(not(feature = "stats"))]
+        guard.defer_destroy(node);
+        #[cfg(feature = "stats")]
+        {
+            let retired = self.counters.retiring();
+            guard.defer_unchecked(move || {
+                drop(node.into_owned());
+                crate::metrics::reclaimed(&retired);
+            });
         }
-        println!(" size:{} actual: {}", self.size(), actual_len - 1);
     }
-}
 
-#[cfg(test)]
-mod he_test {
-    use std::{
-        sync::{
-            atomic::{AtomicI32, Ordering},
-            Arc, Barrier,
-        },
-        thread,
-    };
+    /// Pop the front item only if `pred` accepts it, otherwise leave it queued.
+    ///
+    /// `pred` sees the item through the same protocol as `peek_with`.
+    /// If another consumer takes the item first, `pred` is called again
+    /// on the new front item, so it may run several times per call.
+    pub fn pop_if<F>(&self, mut pred: F) -> Option<T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let guard = &self.pin();
+        let backoff = Backoff::new();
+        let data = unsafe {
+            loop {
+                let head = self.head.load(Ordering::Acquire, guard);
+                let next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
 
-    use crate::he_queue::HeQueue;
+                if next.is_null() {
+                    return None;
+                }
 
-    #[test]
-    fn test_single() {
-        let q = HeQueue::new();
-        q.push(1);
-        q.push(1);
-        q.push(4);
-        q.push(5);
-        q.push(1);
-        q.push(4);
-        assert_eq!(q.pop(), Some(1));
-        assert_eq!(q.pop(), Some(1));
-        assert_eq!(q.pop(), Some(4));
-        assert_eq!(q.pop(), Some(5));
-        assert_eq!(q.pop(), Some(1));
-        assert_eq!(q.pop(), Some(4));
-    }
+                // see `take_in`
+                let tail = self.tail.load(Ordering::Acquire, guard);
+                if head == tail {
+                    if self
+                        .tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed, guard)
+                        .is_ok()
+                    {
+                        self.counters.tail_help();
+                    }
+                    continue;
+                }
 
-    #[test]
-    fn test_concurrent_send() {
-        let pad = 100000_u128;
+                let state = &(*next.as_raw()).state;
+                let s = state.load(Ordering::Acquire);
+                if s & TAKEN != 0 {
+                    continue;
+                }
+                if state
+                    .compare_exchange_weak(s, s + READER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    continue;
+                }
+                let accepted = {
+                    let _read = PeekGuard(state);
+                    (*(*next.as_raw()).item.get())
+                        .as_ref()
+                        .is_some_and(&mut pred)
+                };
+                if !accepted {
+                    return None;
+                }
 
-        let p1 = Arc::new(HeQueue::new());
-        let p2 = p1.clone();
-        let c = p1.clone();
-        let ba1 = Arc::new(Barrier::new(3));
-        let ba2 = ba1.clone();
-        let ba3 = ba1.clone();
-        let t1 = thread::spawn(move || {
-            for i in 0..pad {
-                p1.push(i);
+                // `head.next` never changes, so if `head` is still the
+                // sentinel the item taken is the one `pred` accepted
+                if self
+                    .head
+                    .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, guard)
+                    .is_ok()
+                {
+                    break self.claim(head, next, guard);
+                }
+                self.metrics.cas_retry();
+                self.counters.pop_retry();
+                self.backoff.backoff(&backoff);
             }
-            ba1.wait();
-        });
-        let t2 = thread::spawn(move || {
-            for i in pad..(2 * pad) {
-                p2.push(i);
+        };
+        if data.is_some() {
+            self.count_sub(1);
+            self.metrics.popped(1);
+        }
+        data
+    }
+
+    /// Pop up to `max` items under one guard, appending them to `out`.
+    ///
+    /// Stops early once the queue is observed empty, and returns
+    /// how many items were taken.
+    pub fn pop_into(&self, out: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let guard = &self.pin();
+        let mut count = 0;
+        while count < max {
+            match self.take_in(guard) {
+                Some(data) => out.push(data),
+                None => break,
             }
-            ba2.wait();
-        });
-        // receive after send is finished
-        ba3.wait();
-        let mut sum = 0;
-        while let Some(got) = c.pop() {
-            sum += got;
+            count += 1;
         }
-        let _ = t1.join();
-        let _ = t2.join();
-        assert_eq!(sum, (0..(2 * pad)).sum())
+        self.count_sub(count);
+        self.metrics.popped(count);
+        count
     }
 
-    #[test]
-    fn test_mpsc() {
-        let pad = 100_0000u128;
+    /// Move about half of this queue's items to the back of `dst`.
+    ///
+    /// Takes ⌈len/2⌉ items under one guard and appends them to `dst` as a
+    /// single batch. Returns how many were moved, fewer if this queue is
+    /// drained concurrently.
+    pub fn steal_half(&self, dst: &HeQueue<T>) -> usize {
+        let want = self.size().div_ceil(2);
+        let stolen = self.pop_batch(want);
+        let count = stolen.len();
+        dst.push_batch(stolen);
+        count
+    }
 
-        let flag = Arc::new(AtomicI32::new(3));
-        let flag1 = flag.clone();
-        let flag2 = flag.clone();
-        let flag3 = flag.clone();
-        let p1 = Arc::new(HeQueue::new());
-        let p2 = p1.clone();
-        let p3 = p1.clone();
-        let c = p1.clone();
+    pub fn pop_batch(&self, max: usize) -> Vec<T> {
+        let mut out = Vec::new();
+        self.pop_into(&mut out, max);
+        out
+    }
 
-        let t1 = thread::spawn(move || {
-            for i in 0..pad {
-                p1.push(i);
-            }
-            flag1.fetch_sub(1, Ordering::SeqCst);
-        });
+    /// Call `f` on the front item without removing it.
+    ///
+    /// A concurrent `pop` of the peeked item waits until `f` returns,
+    /// so keep `f` short.
+    pub fn peek_with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let guard = &self.pin();
+        unsafe {
+            loop {
+                let head = self.head.load(Ordering::Acquire, guard);
+                let next = (*head.as_raw()).next.load(Ordering::Acquire, guard);
+
+                if next.is_null() {
+                    return None;
+                }
+
+                let state = &(*next.as_raw()).state;
+                let s = state.load(Ordering::Acquire);
+                // already claimed by a pop, which has moved `head` past it
+                if s & TAKEN != 0 {
+                    continue;
+                }
+                if state
+                    .compare_exchange_weak(s, s + READER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let _read = PeekGuard(state);
+                return (*(*next.as_raw()).item.get()).as_ref().map(f);
+            }
+        }
+    }
+
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.peek_with(T::clone)
+    }
+
+    /// Call `f` on each of the first `n` items, front to back, without
+    /// removing them, returning how many it saw.
+    ///
+    /// The walk is one pass under a single guard. Items popped while it
+    /// runs are skipped, so fewer than `n` may be seen even if more are
+    /// queued by the time it returns. Like `peek_with`, a pop of the item
+    /// `f` is looking at waits for it.
+    pub fn for_each_front<F>(&self, n: usize, mut f: F) -> usize
+    where
+        F: FnMut(&T),
+    {
+        let guard = &self.pin();
+        let mut seen = 0;
+        unsafe {
+            let mut node = self.head.load(Ordering::Acquire, guard);
+            while seen < n {
+                // `next` is never changed once set, and the guard keeps
+                // retired nodes alive, so a stale node still leads on
+                node = node.deref().next.load(Ordering::Acquire, guard);
+                if node.is_null() {
+                    break;
+                }
+                let state = &node.deref().state;
+                let mut s = state.load(Ordering::Acquire);
+                let claimed = loop {
+                    if s & TAKEN != 0 {
+                        break false;
+                    }
+                    match state.compare_exchange_weak(
+                        s,
+                        s + READER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => break true,
+                        Err(now) => s = now,
+                    }
+                };
+                if !claimed {
+                    continue;
+                }
+                let _read = PeekGuard(state);
+                if let Some(item) = (*node.deref().item.get()).as_ref() {
+                    f(item);
+                    seen += 1;
+                }
+            }
+        }
+        seen
+    }
+
+    /// Clone up to the first `n` items, front to back, see [`HeQueue::for_each_front`].
+    pub fn peek_n(&self, n: usize) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut items = Vec::new();
+        self.for_each_front(n, |item| items.push(item.clone()));
+        items
+    }
+
+    /// Operation counters since the queue was created, and with the
+    /// `stats` feature the contention counters since the last
+    /// [`HeQueue::reset_stats`].
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            #[cfg(feature = "stats")]
+            contention: self.counters.stats(),
+            ..self.metrics.stats()
+        }
+    }
+
+    /// A rough estimate of the heap memory held by the queue, in bytes.
+    ///
+    /// Counts the queue itself and one node per item plus the sentinel,
+    /// and with the `stats` feature also the popped nodes the epoch
+    /// collector has not freed yet. Items' own heap allocations are not
+    /// included.
+    pub fn approx_memory_bytes(&self) -> usize {
+        let nodes = self.len() + 1;
+        #[cfg(feature = "stats")]
+        let nodes = nodes + self.counters.retired();
+        mem::size_of::<Self>() + nodes * mem::size_of::<Node<T>>()
+    }
+
+    /// Popped nodes waiting for the epoch collector to free them.
+    #[cfg(feature = "stats")]
+    pub fn retired_nodes(&self) -> usize {
+        self.counters.retired()
+    }
+
+    /// Zero the contention counters in [`HeQueue::stats`], e.g. between
+    /// samples. The operation counters keep running.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.counters.reset()
+    }
+}
+
+// parking consumers need `std`'s threads
+#[cfg(feature = "std")]
+impl<T> HeQueue<T> {
+    /// Pop the front item, parking the thread until one is pushed.
+    pub fn pop_blocking(&self) -> T {
+        loop {
+            if let Some(data) = self.pop_wait(None, false) {
+                return data;
+            }
+        }
+    }
+
+    /// Pop the front item, parking the thread until one is pushed
+    /// or `deadline` passes.
+    pub fn pop_deadline(&self, deadline: Instant) -> Option<T> {
+        self.pop_wait(Some(deadline), false)
+    }
+
+    // like `pop_blocking`, but gives up with `None` once the queue is
+    // closed and drained
+    pub(crate) fn pop_until_closed(&self) -> Option<T> {
+        self.pop_wait(None, true)
+    }
+
+    /// Pop the front item, parking the thread for at most `dur`
+    /// until one is pushed.
+    ///
+    /// A zero `dur` is just [`pop`](Self::pop).
+    pub fn pop_timeout(&self, dur: Duration) -> Option<T> {
+        if dur.is_zero() {
+            return self.pop();
+        }
+        // too far out to represent is as good as forever
+        self.pop_wait(Instant::now().checked_add(dur), false)
+    }
+
+    // spin for a while, then park until a push unparks us
+    fn pop_wait(&self, deadline: Option<Instant>, until_closed: bool) -> Option<T> {
+        let backoff = Backoff::new();
+        let mut notified = false;
+        let data = loop {
+            if let Some(data) = self.pop() {
+                return Some(data);
+            }
+            if until_closed && self.is_finished() {
+                // an accepted item may have landed since
+                return self.pop();
+            }
+            let expired = deadline.is_some_and(|d| Instant::now() >= d);
+            if expired {
+                break None;
+            }
+            if !backoff.is_completed() {
+                backoff.snooze();
+                continue;
+            }
+
+            // register before the last check, so a push landing between
+            // it and `park` unparks us, and `park` returns at once
+            self.waiters.register();
+            let data = self.pop();
+            // `close` notifies after setting the flag, as does the last
+            // push in flight once closed, so checking after registering
+            // can't miss either
+            if data.is_none() && !(until_closed && self.is_finished()) {
+                match deadline {
+                    Some(d) => thread::park_timeout(d.saturating_duration_since(Instant::now())),
+                    None => thread::park(),
+                }
+            }
+            // a no-op if a push already took us off the list
+            notified = !self.waiters.unregister();
+            if data.is_some() {
+                break data;
+            }
+        };
+        // a push picked us to wake, but we leave without its item: the
+        // re-check pop already had one, or someone else took it before we
+        // gave up. Pass the wakeup on so no other parked consumer sleeps
+        // through a queued item
+        if notified {
+            self.waiters.notify_one();
+        }
+        data
+    }
+
+    /// Turn the queue into cloneable push and pop handles, shaped like
+    /// an mpsc channel, see [`he_channel`](crate::he_channel).
+    pub fn split(self) -> (Sender<T>, Receiver<T>) {
+        he_channel::from_queue(self)
+    }
+}
+
+// repin the drain's guard every so often,
+// so a long drain does not hold back garbage collection
+const DRAIN_REPIN: usize = 128;
+
+/// Iterator returned by [`HeQueue::pop_all`].
+pub type PopAll<'a, T> = Drain<'a, T>;
+
+/// Iterator returned by [`HeQueue::drain`].
+pub struct Drain<'a, T> {
+    queue: &'a HeQueue<T>,
+    guard: Guard,
+    // items popped since the last repin, only taken off `len` in one go
+    // then, or once the queue runs dry or the drain is dropped
+    popped: usize,
+}
+
+impl<T> HeQueue<T> {
+    /// Pop items until the queue is observed empty.
+    ///
+    /// Concurrent producers may keep pushing, the iterator simply ends
+    /// at the first `pop` that finds nothing.
+    ///
+    /// The items it pops are only taken off the length counter in batches
+    /// of up to 128, when the queue runs dry, or when the drain is dropped.
+    /// So a drain stopped partway but still held leaves `len` too high:
+    /// `is_empty` may say `false` on an empty queue, and `try_push` on a
+    /// bounded one may refuse items with [`PushError::Full`] early. Drop
+    /// the drain once done with it.
+    pub fn drain(&self) -> Drain<'_, T> {
+        Drain {
+            queue: self,
+            guard: self.pin(),
+            popped: 0,
+        }
+    }
+
+    /// Pop items lazily, one per `next`, for chaining into adaptors.
+    ///
+    /// The same iterator as [`HeQueue::drain`]: it only pops what is
+    /// asked for, so stopping early, e.g. with `take` or `take_while`,
+    /// leaves the remaining items queued, and `len` lags the same way
+    /// until it is dropped.
+    pub fn pop_all(&self) -> PopAll<'_, T> {
+        self.drain()
+    }
+
+    /// Keep only the items `pred` accepts, in order, dropping the rest.
+    ///
+    /// Taking `&mut self` means no other thread can be using the queue,
+    /// so rejected nodes are unlinked on the spot, and freed once the
+    /// list is whole again.
+    pub fn retain<F>(&mut self, mut pred: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut r = Retain {
+                prev: self.head.load(Ordering::Relaxed, guard),
+                queue: self,
+                removed: Vec::new(),
+            };
+            loop {
+                let node = r.prev.deref().next.load(Ordering::Relaxed, guard);
+                if node.is_null() {
+                    break;
+                }
+                if (*node.deref().item.get()).as_ref().is_some_and(&mut pred) {
+                    r.prev = node;
+                    continue;
+                }
+                let next = node.deref().next.load(Ordering::Relaxed, guard);
+                r.prev.deref().next.store(next, Ordering::Relaxed);
+                r.removed.push(node.into_owned());
+            }
+        }
+    }
+}
+
+// fixes `tail` and `len` before any rejected item is dropped, on the way
+// out of `retain` or while unwinding from a panicking `pred`, so a
+// panicking `T::drop` can't leave `tail` on a freed node either
+struct Retain<'a, T> {
+    queue: &'a mut HeQueue<T>,
+    // the last node kept so far, everything after it is still linked
+    prev: Shared<'a, Node<T>>,
+    removed: Vec<Owned<Node<T>>>,
+}
+
+impl<T> Drop for Retain<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            // `pred` may have panicked short of the end
+            let mut last = self.prev;
+            loop {
+                let next = last.deref().next.load(Ordering::Relaxed, guard);
+                if next.is_null() {
+                    break;
+                }
+                last = next;
+            }
+            self.queue.tail.store(last, Ordering::Relaxed);
+        }
+        self.queue.count_sub(self.removed.len());
+        // the queue is whole again, the items may go now
+        self.removed.clear();
+    }
+}
+
+impl<T> Drain<'_, T> {
+    // `len` lags behind by `popped` until this, but never drops below
+    // what is actually queued
+    fn settle(&mut self) {
+        self.queue.count_sub(self.popped);
+        self.queue.metrics.popped(self.popped);
+        self.popped = 0;
+    }
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.popped == DRAIN_REPIN {
+            self.settle();
+            self.guard.repin();
+        }
+        let item = self.queue.take_in(&self.guard);
+        match item {
+            Some(_) => self.popped += 1,
+            None => self.settle(),
+        }
+        item
+    }
+
+    // only a snapshot, other consumers and producers may change it at any time
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        #[cfg(not(feature = "he-no-len"))]
+        let len = self.queue.size().saturating_sub(self.popped);
+        // counted by walking the list, so already up to date,
+        // unless the queue is bounded and keeps a counter after all
+        #[cfg(feature = "he-no-len")]
+        let len = match self.queue.bounded_len {
+            Some(_) => self.queue.size().saturating_sub(self.popped),
+            None => self.queue.size(),
+        };
+        (len, None)
+    }
+}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        self.settle();
+    }
+}
+
+/// Iterator returned by [`HeQueue::iter`].
+pub struct Iter<'a, T> {
+    // the node whose successor is visited next, kept alive by `guard`
+    cur: *const Node<T>,
+    guard: Guard,
+    queue: &'a HeQueue<T>,
+}
+
+/// A queued item borrowed by [`Iter`].
+///
+/// A `pop` of this item waits until the `ItemRef` is dropped,
+/// so never pop the same queue while holding one on that thread.
+pub struct ItemRef<'a, T> {
+    // released before `_guard` unpins, as it lives in the node
+    _read: PeekGuard<'a>,
+    item: &'a T,
+    _guard: Guard,
+}
+
+impl<T> Deref for ItemRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item
+    }
+}
+
+impl<T> HeQueue<T> {
+    /// Walk the queued items from front to back without removing them.
+    ///
+    /// The iterator is not a snapshot: items popped concurrently may still
+    /// be yielded, and items pushed concurrently may be missed.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let guard = self.pin();
+        let cur = self.head.load(Ordering::Acquire, &guard).as_raw();
+        Iter {
+            cur,
+            guard,
+            queue: self,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = ItemRef<'a, T>;
+
+    fn next(&mut self) -> Option<ItemRef<'a, T>> {
+        // nodes reachable from one loaded under `guard` are only retired
+        // after it, so following `next` past popped nodes is fine
+        unsafe {
+            loop {
+                let next = (*self.cur).next.load(Ordering::Acquire, &self.guard);
+                if next.is_null() {
+                    return None;
+                }
+                self.cur = next.as_raw();
+                let node: &'a Node<T> = &*self.cur;
+
+                // take a read like `peek_with`, skipping claimed items
+                let mut s = node.state.load(Ordering::Acquire);
+                while s & TAKEN == 0 {
+                    match node.state.compare_exchange_weak(
+                        s,
+                        s + READER,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            let read = PeekGuard(&node.state);
+                            if let Some(item) = (*node.item.get()).as_ref() {
+                                return Some(ItemRef {
+                                    _read: read,
+                                    item,
+                                    // the node must outlive the iterator's guard
+                                    _guard: self.queue.pin(),
+                                });
+                            }
+                            break;
+                        }
+                        Err(actual) => s = actual,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Stream returned by [`HeQueue::stream`].
+#[cfg(feature = "async")]
+pub struct HeStream<T> {
+    queue: Arc<HeQueue<T>>,
+    // listed in the queue's wakers at most once, however often we poll
+    slot: Arc<WakerSlot>,
+}
+
+#[cfg(feature = "async")]
+impl<T> HeQueue<T> {
+    /// Pop items as a `Stream`, which ends once the queue is closed and empty.
+    ///
+    /// Several streams may consume the same queue, each item is
+    /// yielded by exactly one of them.
+    pub fn stream(self: Arc<Self>) -> HeStream<T> {
+        HeStream {
+            queue: self,
+            slot: Arc::default(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> Stream for HeStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let queue = &self.queue;
+        if let Some(data) = queue.pop() {
+            return Poll::Ready(Some(data));
+        }
+        // look again after registering, a push in between
+        // would not have seen our waker
+        queue.wakers.register(&self.slot, cx.waker());
+        if let Some(data) = queue.pop() {
+            return Poll::Ready(Some(data));
+        }
+        if queue.is_finished() {
+            // every accepted item is visible by now
+            return Poll::Ready(queue.pop());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> HeQueue<T> {
+    // trade lists with an empty queue of the other flavour, returning
+    // how many items were handed over, see `From<HeQueue<T>> for CrsQueue<T>`
+    pub(crate) fn swap_list(&mut self, head: &mut NodePtr<T>, tail: &mut NodePtr<T>) -> usize {
+        let len = self.len();
+        mem::swap(&mut *self.head, head);
+        mem::swap(&mut *self.tail, tail);
+        #[cfg(not(feature = "he-no-len"))]
+        self.len.store(0, Ordering::Relaxed);
+        #[cfg(feature = "he-no-len")]
+        if let Some(len) = &self.bounded_len {
+            len.store(0, Ordering::Relaxed);
+        }
+        len
+    }
+}
+
+// both flavours share `Node`, so the list is relinked rather than copied
+#[cfg(feature = "std")]
+impl<T> From<CrsQueue<T>> for HeQueue<T> {
+    fn from(mut queue: CrsQueue<T>) -> Self {
+        let mut q = HeQueue::new();
+        let len = queue.swap_list(&mut q.head, &mut q.tail);
+        q.count_add(len);
+        q
+    }
+}
+
+impl<T> FromIterator<T> for HeQueue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let q = Self::new();
+        // nobody else can see the queue yet, so neither pinning
+        // nor anything stronger than plain stores is needed
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut tail = q.tail.load(Ordering::Relaxed, guard);
+            let mut count = 0;
+            for data in iter {
+                let node = Owned::new(Node::new(data)).into_shared(guard);
+                (*tail.as_raw()).next.store(node, Ordering::Relaxed);
+                tail = node;
+                count += 1;
+            }
+            q.tail.store(tail, Ordering::Relaxed);
+            q.count_add(count);
+            q.metrics.pushed(count);
+        }
+        q
+    }
+}
+
+// items become visible to consumers one by one as the iterator yields them,
+// use `push_batch` to publish them all at once instead
+impl<T> Extend<T> for &HeQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.push(data);
+        }
+    }
+}
+
+impl<T> Extend<T> for HeQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        (&*self).extend(iter)
+    }
+}
+
+/// A best-effort snapshot: items popped or pushed by other threads while
+/// the clone walks the list may or may not make it in, but those that do
+/// keep their order.
+impl<T: Clone> Clone for HeQueue<T> {
+    fn clone(&self) -> Self {
+        // built like `self`, so it keeps the backoff, orderings and bound,
+        // and under `he-no-len` the counter a bounded queue needs
+        let q = Self::build(self.backoff, self.orderings, self.capacity);
+        // `iter` walks under one guard and reads each item the way
+        // `peek_with` does, so a racing pop waits for the copy to finish
+        q.push_batch(self.iter().map(|item| item.clone()));
+        q.closed.store(self.is_closed(), Ordering::Relaxed);
+        q
+    }
+}
+
+/// Serialized as a sequence of the queued items, front first.
+///
+/// Like [`Clone`], this is a best-effort snapshot when other threads are
+/// pushing or popping. Some formats need the length up front, so every
+/// item is held the way [`Iter`] holds it from the walk until it has been
+/// written, and a pop reaching it busy-waits until then, serializer I/O
+/// included. Items are let go one by one as they are written, front
+/// first, so pops only wait on the part still ahead of the serializer.
+/// Where even that is too long, serialize a [`clone`](Clone::clone),
+/// which holds each item only while copying it.
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for HeQueue<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let items: Vec<_> = self.iter().collect();
+        let mut seq = serializer.serialize_seq(Some(items.len()))?;
+        // by value, so each item's hold is dropped once it is written
+        for item in items {
+            seq.serialize_element(&*item)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for HeQueue<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from_iter)
+    }
+}
+
+impl<T> Drop for HeQueue<T> {
+    fn drop(&mut self) {
+        // nodes already popped were handed to the epoch collector,
+        // everything from the sentinel on is ours alone to free right away
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let next = node.deref().next.load(Ordering::Relaxed, guard);
+                drop(node.into_owned());
+                node = next;
+            }
+        }
+    }
+}
+
+/// The producer half returned by [`HeQueue::split_spsc`].
+pub struct SpscSender<T> {
+    queue: Arc<HeQueue<T>>,
+}
+
+/// The consumer half returned by [`HeQueue::split_spsc`].
+///
+/// Dropping it drops every item queued at that point.
+pub struct SpscReceiver<T> {
+    queue: Arc<HeQueue<T>>,
+}
+
+// each half is the only one touching its end of the list,
+// so only the items themselves cross threads
+unsafe impl<T: Send> Send for SpscSender<T> {}
+unsafe impl<T: Send> Send for SpscReceiver<T> {}
+
+impl<T> HeQueue<T> {
+    /// Split the queue into a single producer and a single consumer.
+    ///
+    /// With one thread at each end, neither `push` nor `pop` needs a CAS
+    /// or an epoch pin: the producer only writes `next` of the last node,
+    /// which the consumer never frees, as it always stays behind it.
+    /// Items already queued stay queued.
+    pub fn split_spsc(self) -> (SpscSender<T>, SpscReceiver<T>) {
+        // `tail` may lag behind the last node, see `append_chain`
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut tail = self.tail.load(Ordering::Relaxed, guard);
+            loop {
+                let next = tail.deref().next.load(Ordering::Relaxed, guard);
+                if next.is_null() {
+                    break;
+                }
+                tail = next;
+            }
+            self.tail.store(tail, Ordering::Relaxed);
+        }
+        let queue = Arc::new(self);
+        let tx = SpscSender {
+            queue: queue.clone(),
+        };
+        (tx, SpscReceiver { queue })
+    }
+}
+
+impl<T> SpscSender<T> {
+    pub fn push(&mut self, data: T) {
+        let queue = &self.queue;
+        unsafe {
+            let guard = epoch::unprotected();
+            let node = Owned::new(Node::new(data)).into_shared(guard);
+            let tail = queue.tail.load(Ordering::Relaxed, guard);
+            // publishes the item to the consumer
+            tail.deref().next.store(node, Ordering::Release);
+            queue.tail.store(node, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> SpscReceiver<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let queue = &self.queue;
+        unsafe {
+            let guard = epoch::unprotected();
+            let head = queue.head.load(Ordering::Relaxed, guard);
+            let next = head.deref().next.load(Ordering::Acquire, guard);
+            if next.is_null() {
+                return None;
+            }
+            let data = (*next.deref().item.get()).take();
+            queue.head.store(next, Ordering::Relaxed);
+            // the producer is already past `head`, nobody else can reach it
+            drop(head.into_owned());
+            data
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unsafe {
+            let guard = epoch::unprotected();
+            let head = self.queue.head.load(Ordering::Relaxed, guard);
+            head.deref().next.load(Ordering::Acquire, guard).is_null()
+        }
+    }
+}
+
+impl<T> Drop for SpscReceiver<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Iterator returned by [`HeQueue::into_iter`].
+pub struct IntoIter<T> {
+    // left valid after every step, so its drop frees whatever remains
+    queue: HeQueue<T>,
+}
+
+impl<T> HeQueue<T> {
+    /// Move every item out, front to back, into a `Vec`.
+    pub fn into_vec(self) -> Vec<T> {
+        let mut out = Vec::with_capacity(self.size());
+        out.extend(self);
+        out
+    }
+}
+
+impl<T> IntoIterator for HeQueue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { queue: self }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    // an owned queue needs neither pinning nor CAS,
+    // the old sentinel is freed on the spot
+    fn next(&mut self) -> Option<T> {
+        let queue = &self.queue;
+        unsafe {
+            let guard = epoch::unprotected();
+            let head = queue.head.load(Ordering::Relaxed, guard);
+            let next = head.deref().next.load(Ordering::Relaxed, guard);
+            if next.is_null() {
+                return None;
+            }
+            let data = (*next.deref().item.get()).take();
+            queue.head.store(next, Ordering::Relaxed);
+            // a lagging `tail` must not be left on the freed node
+            if queue.tail.load(Ordering::Relaxed, guard) == head {
+                queue.tail.store(next, Ordering::Relaxed);
+            }
+            queue.count_sub(1);
+            drop(head.into_owned());
+            data
+        }
+    }
+
+    // exact, nobody else can touch the queue
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.queue.size();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> HeQueue<T> {
+    /// Count the nodes reachable from `head` under a single guard.
+    ///
+    /// With no concurrent operations, `nodes` and `counted_len` agree.
+    pub fn walk(&self) -> WalkReport {
+        WalkReport {
+            nodes: self.count_nodes(),
+            counted_len: self.size(),
+        }
+    }
+
+    // item nodes linked after the sentinel
+    fn count_nodes(&self) -> usize {
+        let guard = &self.pin();
+        let mut node = self.head.load(Ordering::Acquire, guard);
+
+        let mut nodes = 0;
+        unsafe {
+            loop {
+                node = (*node.as_raw()).next.load(Ordering::Acquire, guard);
+                if node.is_null() {
+                    break;
+                }
+                nodes += 1;
+            }
+        }
+        nodes
+    }
+
+    /// Address of the node `head` points at, the sentinel, for debugging.
+    pub fn head_addr(&self) -> usize {
+        let guard = &self.pin();
+        self.head.load(Ordering::Acquire, guard).as_raw() as usize
+    }
+
+    /// Address of the node `tail` points at, for debugging.
+    ///
+    /// Once pushes and pops have quiesced, this is the last node,
+    /// and for an empty queue the same as [`HeQueue::head_addr`].
+    pub fn tail_addr(&self) -> usize {
+        let guard = &self.pin();
+        self.tail.load(Ordering::Acquire, guard).as_raw() as usize
+    }
+}
+
+/// Returned by [`HeQueue::walk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkReport {
+    /// Item nodes linked after the sentinel.
+    pub nodes: usize,
+    /// The length counter, read after the walk.
+    ///
+    /// With the `he-no-len` feature, a second walk.
+    pub counted_len: usize,
+}
+
+// the items shown by `Debug`, longer queues are cut off with `..`
+const DEBUG_ITEMS: usize = 16;
+
+struct DebugItems<'a, T>(&'a HeQueue<T>);
+
+impl<T: fmt::Debug> fmt::Debug for DebugItems<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut iter = self.0.iter();
+        for item in iter.by_ref().take(DEBUG_ITEMS) {
+            list.entry(&*item);
+        }
+        if iter.next().is_some() {
+            list.finish_non_exhaustive()
+        } else {
+            list.finish()
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for HeQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeQueue")
+            .field("len", &self.len())
+            .field("items", &DebugItems(self))
+            .finish()
+    }
+}
+
+// the `std`-less build, where pinning goes through the queue's collector
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_test {
+    use alloc::vec::Vec;
+
+    use crate::he_queue::HeQueue;
+
+    #[test]
+    fn test_push_pop() {
+        let q = HeQueue::new();
+        assert!(q.is_empty());
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push(i);
+        }
+        assert_eq!(q.len(), 6);
+        assert_eq!(q.peek(), Some(1));
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.drain().collect::<Vec<_>>(), [1, 4, 5, 1, 4]);
+        assert_eq!(q.pop(), None);
+    }
+
+    #[test]
+    fn test_with_guard() {
+        let q = HeQueue::new();
+        let handle = q.collector().register();
+        let guard = &handle.pin();
+        for i in 0..100 {
+            q.push_with(i, guard);
+        }
+        for i in 0..100 {
+            assert_eq!(q.pop_with(guard), Some(i));
+        }
+        assert_eq!(q.pop_with(guard), None);
+    }
+
+    #[test]
+    #[should_panic = "queue's collector"]
+    fn test_with_other_queues_guard() {
+        let q = HeQueue::new();
+        let other = HeQueue::<i32>::new();
+        let handle = other.collector().register();
+        q.push_with(1, &handle.pin());
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod he_test {
+    use std::{
+        mem, panic,
+        sync::{
+            atomic::{AtomicI32, AtomicUsize, Ordering},
+            mpsc, Arc, Barrier,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crossbeam_epoch as epoch;
+    use proptest::prelude::*;
+
+    #[cfg(feature = "stats")]
+    use crate::metrics::HeStats;
+    use crate::{
+        crs_queue::CrsQueue,
+        he_queue::{BackoffPolicy, HeQueue, OrderingProfile, PopError, PushError, WalkReport},
+        node::Node,
+        test_util::{check_logs, ops, replay, replay_model},
+    };
+
+    #[test]
+    fn test_single() {
+        let q = HeQueue::new();
+        q.push(1);
+        q.push(1);
+        q.push(4);
+        q.push(5);
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.pop(), Some(4));
+        assert_eq!(q.pop(), Some(5));
+        assert_eq!(
+            q.walk(),
+            WalkReport {
+                nodes: 2,
+                counted_len: 2
+            }
+        );
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.pop(), Some(4));
+        assert_eq!(
+            q.walk(),
+            WalkReport {
+                nodes: 0,
+                counted_len: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_debug() {
+        let q = HeQueue::new();
+        assert_eq!(format!("{:?}", q), "HeQueue { len: 0, items: [] }");
+        q.push_batch([1, 1, 4]);
+        assert_eq!(format!("{:?}", q), "HeQueue { len: 3, items: [1, 1, 4] }");
+
+        let q: HeQueue<_> = (0..100).collect();
+        let items: Vec<_> = (0..16).map(|i| i.to_string()).collect();
+        assert_eq!(
+            format!("{:?}", q),
+            format!("HeQueue {{ len: 100, items: [{}, ..] }}", items.join(", "))
+        );
+    }
+
+    #[test]
+    fn test_concurrent_send() {
+        let pad = if cfg!(miri) { 64 } else { 100000_u128 };
+
+        let p1 = Arc::new(HeQueue::new());
+        let p2 = p1.clone();
+        let c = p1.clone();
+        let ba1 = Arc::new(Barrier::new(3));
+        let ba2 = ba1.clone();
+        let ba3 = ba1.clone();
+        let t1 = thread::spawn(move || {
+            for i in 0..pad {
+                p1.push(i);
+            }
+            ba1.wait();
+        });
+        let t2 = thread::spawn(move || {
+            for i in pad..(2 * pad) {
+                p2.push(i);
+            }
+            ba2.wait();
+        });
+        // receive after send is finished
+        ba3.wait();
+        let report = c.walk();
+        assert_eq!(report.nodes, 2 * pad as usize);
+        assert_eq!(report.nodes, report.counted_len);
+        let mut sum = 0;
+        while let Some(got) = c.pop() {
+            sum += got;
+        }
+        let _ = t1.join();
+        let _ = t2.join();
+        assert_eq!(sum, (0..(2 * pad)).sum())
+    }
+
+    #[test]
+    fn test_mpsc() {
+        let pad = if cfg!(miri) { 64 } else { 100_0000u128 };
+
+        let flag = Arc::new(AtomicI32::new(3));
+        let flag1 = flag.clone();
+        let flag2 = flag.clone();
+        let flag3 = flag.clone();
+        let p1 = Arc::new(HeQueue::new());
+        let p2 = p1.clone();
+        let p3 = p1.clone();
+        let c = p1.clone();
+
+        let t1 = thread::spawn(move || {
+            for i in 0..pad {
+                p1.push(i);
+            }
+            flag1.fetch_sub(1, Ordering::SeqCst);
+        });
         let t2 = thread::spawn(move || {
             for i in pad..(2 * pad) {
                 p2.push(i);
             }
-            flag2.fetch_sub(1, Ordering::SeqCst);
-        });
-        let t3 = thread::spawn(move || {
-            for i in (2 * pad)..(3 * pad) {
-                p3.push(i);
+            flag2.fetch_sub(1, Ordering::SeqCst);
+        });
+        let t3 = thread::spawn(move || {
+            for i in (2 * pad)..(3 * pad) {
+                p3.push(i);
+            }
+            flag3.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        let mut sum = 0;
+        while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+            if let Some(num) = c.pop() {
+                sum += num;
+            }
+        }
+
+        t1.join().unwrap();
+        t2.join().unwrap();
+        t3.join().unwrap();
+        assert_eq!(sum, (0..(3 * pad)).sum());
+    }
+
+    #[test]
+    fn test_mpmc() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000u128 };
+
+        let flag = Arc::new(AtomicI32::new(3));
+        let flag_c = flag.clone();
+        let flag1 = flag.clone();
+        let flag2 = flag.clone();
+        let flag3 = flag.clone();
+
+        let p1 = Arc::new(HeQueue::new());
+        let p2 = p1.clone();
+        let p3 = p1.clone();
+        let c1 = p1.clone();
+        let c2 = p1.clone();
+
+        let producer1 = thread::spawn(move || {
+            for i in 0..pad {
+                p1.push(i);
+            }
+            flag1.fetch_sub(1, Ordering::SeqCst);
+        });
+        let producer2 = thread::spawn(move || {
+            for i in pad..(2 * pad) {
+                p2.push(i);
+            }
+            flag2.fetch_sub(1, Ordering::SeqCst);
+        });
+        let producer3 = thread::spawn(move || {
+            for i in (2 * pad)..(3 * pad) {
+                p3.push(i);
+            }
+            flag3.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        let consumer = thread::spawn(move || {
+            let mut sum = 0;
+            while flag_c.load(Ordering::SeqCst) != 0 || !c2.is_empty() {
+                if let Some(num) = c2.pop() {
+                    sum += num;
+                }
+            }
+            sum
+        });
+
+        let mut sum = 0;
+        while flag.load(Ordering::SeqCst) != 0 || !c1.is_empty() {
+            if let Some(num) = c1.pop() {
+                sum += num;
+            }
+        }
+
+        producer1.join().unwrap();
+        producer2.join().unwrap();
+        producer3.join().unwrap();
+
+        let s = consumer.join().unwrap();
+        sum += s;
+        assert_eq!(sum, (0..(3 * pad)).sum());
+    }
+
+    #[test]
+    fn test_relaxed_orderings() {
+        let producers = 3;
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        let q = Arc::new(HeQueue::new_relaxed());
+        let pushed: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push((n, i));
+                    }
+                })
+            })
+            .collect();
+        let popped: Vec<_> = (0..3)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut log = vec![];
+                    loop {
+                        match q.pop_result() {
+                            Ok(item) => log.push(item),
+                            Err(PopError::Empty) => thread::yield_now(),
+                            Err(PopError::Closed) => return log,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for p in pushed {
+            p.join().unwrap();
+        }
+        // consumers seeing the relaxed profile's close must still see
+        // every push that finished before it
+        q.close();
+        let logs: Vec<_> = popped.into_iter().map(|c| c.join().unwrap()).collect();
+        check_logs(producers, pad, &logs);
+        assert_eq!(q.len(), 0);
+    }
+
+    // with `he-no-len` the walk isn't a snapshot, so there are no bounds
+    #[cfg(not(feature = "he-no-len"))]
+    #[test]
+    fn test_size_bounds() {
+        let pad = if cfg!(miri) { 64 } else { 100_000 };
+        crate::test_util::check_len_bounds(Arc::new(HeQueue::new()), 8, pad);
+    }
+
+    #[test]
+    fn test_len() {
+        let q = HeQueue::new();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.size(), 2);
+        assert!(!q.is_empty());
+        q.pop();
+        q.pop();
+        q.pop();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+    }
+
+    #[cfg(all(debug_assertions, not(feature = "he-no-len")))]
+    #[test]
+    #[should_panic(expected = "length underflow")]
+    fn test_len_underflow() {
+        let q = HeQueue::new();
+        q.push(1);
+        q.push(4);
+        // a counter out of step with the list, as a bug would leave it
+        q.len.store(1, Ordering::Relaxed);
+        q.pop();
+        q.pop();
+    }
+
+    // every pop path uncounts, and debug builds assert on each of them
+    #[test]
+    fn test_len_no_underflow() {
+        let threads = 4;
+        let pad = if cfg!(miri) { 32 } else { 20_000usize };
+
+        let q = Arc::new(HeQueue::new());
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut buf = vec![];
+                    for i in 0..pad {
+                        match (t + i) % 4 {
+                            0 => q.push(i),
+                            1 => q.push_batch([i, i]),
+                            _ => {
+                                q.pop();
+                                q.pop_into(&mut buf, 2);
+                                buf.clear();
+                            }
+                        }
+                        assert!(q.len() <= 3 * threads * pad);
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let left = q.len();
+        assert_eq!(q.drain().count(), left);
+        assert_eq!(q.len(), 0);
+    }
+
+    #[test]
+    fn test_peek_n() {
+        let q = HeQueue::new();
+        assert!(q.peek_n(3).is_empty());
+        q.push_batch([1, 1, 4, 5, 1, 4]);
+        assert_eq!(q.peek_n(0), vec![]);
+        assert_eq!(q.peek_n(3), vec![1, 1, 4]);
+        // fewer than asked for
+        assert_eq!(q.peek_n(10), vec![1, 1, 4, 5, 1, 4]);
+        assert_eq!(q.len(), 6);
+
+        q.pop();
+        let mut sum = 0;
+        assert_eq!(q.for_each_front(2, |x| sum += x), 2);
+        assert_eq!(sum, 1 + 4);
+        assert_eq!(q.pop(), Some(1));
+    }
+
+    #[test]
+    fn test_peek_n_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 20_000u64 };
+        let window = 8;
+
+        let q = Arc::new(HeQueue::new());
+        let done = Arc::new(AtomicI32::new(0));
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let q = q.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut popped = 0;
+                    while done.load(Ordering::SeqCst) == 0 || !q.is_empty() {
+                        if q.pop().is_some() {
+                            popped += 1;
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+        let p = q.clone();
+        let d = done.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..pad {
+                p.push(i);
+            }
+            d.store(1, Ordering::SeqCst);
+        });
+
+        // a single producer, so whatever is seen is in order
+        while done.load(Ordering::SeqCst) == 0 {
+            let front = q.peek_n(window);
+            assert!(front.len() <= window);
+            assert!(front.windows(2).all(|w| w[0] < w[1]));
+        }
+        producer.join().unwrap();
+        let popped: u64 = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+        assert_eq!(popped, pad);
+        assert!(q.peek_n(window).is_empty());
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut q = HeQueue::from_iter([1, 2, 3, 4, 5, 6]);
+        // front, middle and back
+        q.retain(|x| ![1, 4, 6].contains(x));
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.peek_n(10), vec![2, 3, 5]);
+        // `tail` was moved back onto 5, so pushes link after it
+        q.push(7);
+        q.retain(|_| true);
+        assert_eq!(q.peek_n(10), vec![2, 3, 5, 7]);
+
+        q.retain(|_| false);
+        assert!(q.is_empty());
+        assert!(q.is_structurally_empty());
+        assert_eq!(q.pop(), None);
+        q.push(8);
+        assert_eq!(q.pop(), Some(8));
+
+        // the rejected are dropped by `retain`, the rest with the queue
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Job(usize);
+        impl Drop for Job {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let mut q: HeQueue<_> = (0..10).map(Job).collect();
+        q.retain(|job| job.0 % 3 == 0);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 6);
+        assert_eq!(q.len(), 4);
+        drop(q);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_retain_panics() {
+        // a panicking `pred` leaves what it has not looked at yet
+        let mut q = HeQueue::from_iter([1, 2, 3, 4, 5, 6]);
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            q.retain(|&x| if x == 4 { panic!("boom") } else { x % 2 == 0 })
+        }));
+        assert!(res.is_err());
+        assert_eq!(q.len(), 4);
+        q.push(7);
+        assert_eq!(q.peek_n(10), vec![2, 4, 5, 6, 7]);
+
+        // a panicking drop of the last node still leaves `tail` on a live one
+        struct Bomb(i32);
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                if self.0 == 3 && !thread::panicking() {
+                    panic!("boom");
+                }
+            }
+        }
+        let mut q = HeQueue::from_iter([Bomb(1), Bomb(2), Bomb(3)]);
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| q.retain(|b| b.0 != 3)));
+        assert!(res.is_err());
+        assert_eq!(q.len(), 2);
+        q.push(Bomb(4));
+        let left: Vec<_> = q.drain().map(|b| b.0).collect();
+        assert_eq!(left, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_retain_then_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 10_000u64 };
+
+        let mut q: HeQueue<u64> = (0..100).collect();
+        q.retain(|x| x % 2 == 0);
+        let q = Arc::new(q);
+        let producers: Vec<_> = (0..2)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push(1000 + n * pad + i);
+                    }
+                })
+            })
+            .collect();
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let mut got = vec![];
+            while got.len() < 50 + 2 * pad as usize {
+                if let Some(i) = c.pop() {
+                    got.push(i);
+                }
+            }
+            got
+        });
+        for p in producers {
+            p.join().unwrap();
+        }
+        let got = consumer.join().unwrap();
+        // the survivors come out first, in order
+        assert_eq!(got[..50], (0..100).step_by(2).collect::<Vec<_>>());
+        let mut rest = got[50..].to_vec();
+        rest.sort_unstable();
+        assert_eq!(rest, (1000..1000 + 2 * pad).collect::<Vec<_>>());
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_pop_if() {
+        let q = HeQueue::new();
+        assert_eq!(q.pop_if(|_| true), None);
+        q.push_batch([1, 5, 2]);
+        assert_eq!(q.pop_if(|x| *x < 3), Some(1));
+        // rejected, and still there for the next pop
+        assert_eq!(q.pop_if(|x| *x < 3), None);
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.pop(), Some(5));
+        assert_eq!(q.pop_if(|x| *x < 3), Some(2));
+        assert!(q.is_empty());
+
+        // only take a job while there's budget left for its cost
+        let jobs = HeQueue::from_iter([3, 4, 2, 6]);
+        let mut budget = 10;
+        let mut done = vec![];
+        while let Some(cost) = jobs.pop_if(|cost| *cost <= budget) {
+            budget -= cost;
+            done.push(cost);
+        }
+        assert_eq!(done, vec![3, 4, 2]);
+        assert_eq!(budget, 1);
+        assert_eq!(jobs.pop(), Some(6));
+    }
+
+    #[test]
+    fn test_pop_if_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 20_000u64 };
+
+        let q = Arc::new(HeQueue::new());
+        let taken = Arc::new(AtomicUsize::new(0));
+        let consumer = |picky: bool| {
+            let q = q.clone();
+            let taken = taken.clone();
+            thread::spawn(move || {
+                let mut got = vec![];
+                while taken.load(Ordering::SeqCst) < pad as usize {
+                    let item = if picky {
+                        q.pop_if(|x| x % 2 == 0)
+                    } else {
+                        q.pop()
+                    };
+                    if let Some(i) = item {
+                        got.push(i);
+                        taken.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                got
+            })
+        };
+        let picky = [consumer(true), consumer(true)];
+        let plain = [consumer(false), consumer(false)];
+        for i in 0..pad {
+            q.push(i);
+        }
+
+        let mut all = vec![];
+        for c in picky {
+            let got = c.join().unwrap();
+            // odd items were left for the plain consumers
+            assert!(got.iter().all(|i| i % 2 == 0));
+            all.extend(got);
+        }
+        for c in plain {
+            all.extend(c.join().unwrap());
+        }
+        all.sort();
+        assert_eq!(all, (0..pad).collect::<Vec<_>>());
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_peek() {
+        let q = HeQueue::new();
+        assert_eq!(q.peek(), None);
+        assert_eq!(q.peek_with(|x: &i32| *x), None);
+
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.peek(), Some(1));
+        assert_eq!(q.peek_with(|x| x + 1), Some(2));
+        assert_eq!(q.size(), 2);
+
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.peek(), Some(4));
+        assert_eq!(q.pop(), Some(4));
+        assert_eq!(q.peek(), None);
+    }
+
+    #[test]
+    fn test_concurrent_peek() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000usize };
+
+        let flag = Arc::new(AtomicI32::new(1));
+        let flag1 = flag.clone();
+        let flag_k = flag.clone();
+
+        let p = Arc::new(HeQueue::new());
+        let c = p.clone();
+        let k = p.clone();
+
+        let producer = thread::spawn(move || {
+            for i in 0..pad {
+                p.push(i.to_string());
+            }
+            flag1.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        // owning heap data makes reading a popped item show up under Miri/ASan
+        let peeker = thread::spawn(move || {
+            let mut last = 0;
+            while flag_k.load(Ordering::SeqCst) != 0 || !k.is_empty() {
+                if let Some(n) = k.peek_with(|s: &String| s.parse::<usize>().unwrap()) {
+                    // items are only ever removed from the front
+                    assert!(n >= last);
+                    last = n;
+                }
+            }
+        });
+
+        let mut sum = 0;
+        while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+            if let Some(s) = c.pop() {
+                sum += s.parse::<usize>().unwrap();
+            }
+        }
+
+        producer.join().unwrap();
+        peeker.join().unwrap();
+        assert_eq!(sum, (0..pad).sum());
+    }
+
+    #[test]
+    fn test_drain_to_empty() {
+        // keep it small enough for Miri
+        let (rounds, pad) = if cfg!(miri) { (4, 16) } else { (16, 128) };
+
+        for _ in 0..rounds {
+            let flag = Arc::new(AtomicI32::new(2));
+            let q = Arc::new(HeQueue::new());
+
+            let producers: Vec<_> = (0..2)
+                .map(|n| {
+                    let p = q.clone();
+                    let flag = flag.clone();
+                    thread::spawn(move || {
+                        for i in (n * pad)..((n + 1) * pad) {
+                            p.push(i);
+                            // give the consumers a chance to empty the queue
+                            thread::yield_now();
+                        }
+                        flag.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    let c = q.clone();
+                    let flag = flag.clone();
+                    thread::spawn(move || {
+                        let mut sum = 0;
+                        while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+                            if let Some(num) = c.pop() {
+                                sum += num;
+                            }
+                        }
+                        sum
+                    })
+                })
+                .collect();
+
+            for p in producers {
+                p.join().unwrap();
+            }
+            let sum: usize = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+            assert_eq!(sum, (0..(2 * pad)).sum());
+            assert!(q.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let q = HeQueue::new();
+        assert!(q.iter().next().is_none());
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push(i);
+        }
+        q.pop();
+        let seen: Vec<_> = q.iter().map(|x| *x).collect();
+        assert_eq!(seen, vec![1, 4, 5, 1, 4]);
+        assert_eq!(q.len(), 5);
+
+        let strings = HeQueue::new();
+        strings.push(String::from("114"));
+        strings.push(String::from("514"));
+        let joined: Vec<_> = strings.iter().map(|s| s.clone()).collect();
+        assert_eq!(joined, vec!["114", "514"]);
+        assert_eq!(strings.pop().as_deref(), Some("114"));
+    }
+
+    #[test]
+    fn test_iter_while_draining() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let q = Arc::new(HeQueue::new());
+        for i in 0..pad {
+            q.push(Box::new(i));
+        }
+
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let mut popped = 0;
+            while let Some(x) = c.pop() {
+                assert_eq!(*x, popped);
+                popped += 1;
+            }
+            popped
+        });
+
+        while !q.is_empty() {
+            let mut last = None;
+            for x in q.iter() {
+                let x = **x;
+                assert!(x < pad);
+                // still in push order, even when skipping popped items
+                assert!(last.is_none_or(|l| l < x));
+                last = Some(x);
+            }
+        }
+
+        assert_eq!(consumer.join().unwrap(), pad);
+    }
+
+    #[test]
+    fn test_from_crs_queue() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let crs = CrsQueue::new();
+        // leftover prewarmed nodes stay with, and are freed by, the `CrsQueue`
+        crs.prewarm(8);
+        for i in 0..pad {
+            crs.push(i);
+        }
+        crs.pop();
+        let q = HeQueue::from(crs);
+        assert_eq!(q.len(), pad - 1);
+        q.push(pad);
+        assert_eq!(q.drain().collect::<Vec<_>>(), (1..=pad).collect::<Vec<_>>());
+        assert!(q.is_empty());
+
+        // every item dropped once, whichever queue it ended up in
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let crs = CrsQueue::new();
+        for _ in 0..pad {
+            crs.push(Token);
+        }
+        drop(crs.pop());
+        let q = HeQueue::from(crs);
+        drop(q.pop());
+        let crs = CrsQueue::from(q);
+        drop(crs.pop());
+        let q = HeQueue::from(crs);
+        assert_eq!(q.len(), pad - 3);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+        drop(q);
+        assert_eq!(DROPS.load(Ordering::SeqCst), pad);
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let q: HeQueue<_> = [1, 1, 4, 5, 1, 4].into_iter().collect();
+        assert_eq!(q.size(), 6);
+        let mut popped = vec![];
+        while let Some(x) = q.pop() {
+            popped.push(x);
+        }
+        assert_eq!(popped, vec![1, 1, 4, 5, 1, 4]);
+
+        let q: HeQueue<i32> = std::iter::empty().collect();
+        assert!(q.is_empty());
+        q.push(1);
+        assert_eq!(q.pop(), Some(1));
+
+        // the collected queue is fully usable afterwards
+        let mut q: HeQueue<_> = (0..3).map(|i| i.to_string()).collect();
+        q.extend(["3".to_string()]);
+        q.push("4".to_string());
+        assert_eq!(q.size(), 5);
+        let popped: Vec<_> = q.drain().collect();
+        assert_eq!(popped, vec!["0", "1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_clone() {
+        let q = HeQueue::with_capacity(8);
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push(i.to_string());
+        }
+        q.pop();
+        q.close();
+
+        let c = q.clone();
+        assert_eq!(c.len(), 5);
+        assert!(c.is_closed());
+        assert_eq!(c.capacity(), Some(8));
+        assert_eq!(c.orderings, OrderingProfile::Strong);
+        assert_eq!(
+            HeQueue::<i32>::new_relaxed().clone().orderings,
+            OrderingProfile::Relaxed
+        );
+        let spin = HeQueue::<i32>::with_backoff(BackoffPolicy::Spin);
+        assert_eq!(spin.clone().backoff, BackoffPolicy::Spin);
+        assert_eq!(
+            c.try_push("0".to_string()),
+            Err(PushError::Closed("0".to_string()))
+        );
+
+        // the two are independent
+        assert_eq!(q.pop().as_deref(), Some("1"));
+        let cloned: Vec<_> = c.drain().collect();
+        assert_eq!(cloned, vec!["1", "4", "5", "1", "4"]);
+        assert!(c.is_empty());
+        assert_eq!(q.len(), 4);
+    }
+
+    #[test]
+    fn test_clone_under_load() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let q = Arc::new(HeQueue::new());
+        for i in 0..pad / 2 {
+            q.push(Box::new(i));
+        }
+
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            for i in pad / 2..pad {
+                p.push(Box::new(i));
+            }
+        });
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let mut popped = 0;
+            while popped < pad {
+                if let Some(x) = c.pop() {
+                    assert_eq!(*x, popped);
+                    popped += 1;
+                }
+            }
+        });
+
+        let mut snapshots = 0;
+        while !producer.is_finished() || !consumer.is_finished() || snapshots == 0 {
+            let snapshot = (*q).clone();
+            let len = snapshot.len();
+            let items: Vec<_> = snapshot.drain().map(|x| *x).collect();
+            assert_eq!(items.len(), len);
+            // a subsequence of what was pushed, in push order
+            assert!(items.windows(2).all(|w| w[0] < w[1]));
+            assert!(items.iter().all(|&x| x < pad));
+            snapshots += 1;
+        }
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+        assert!((*q).clone().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let pad = if cfg!(miri) { 64 } else { 100_000usize };
+
+        let q: HeQueue<_> = (0..pad).map(|i| i.to_string()).collect();
+        q.pop();
+        let bytes = bincode::serialize(&q).unwrap();
+        let back: HeQueue<String> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back.len(), pad - 1);
+        assert!(back.drain().eq((1..pad).map(|i| i.to_string())));
+
+        // the original is untouched, and an empty queue round-trips too
+        assert_eq!(q.len(), pad - 1);
+        let empty: HeQueue<u32> =
+            bincode::deserialize(&bincode::serialize(&HeQueue::<u32>::new()).unwrap()).unwrap();
+        assert!(empty.is_empty());
+        empty.push(1);
+        assert_eq!(empty.pop(), Some(1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_lets_go_as_it_writes() {
+        use std::sync::Mutex;
+
+        // writing item 2 stalls until items 0 and 1 have been popped
+        struct Slow {
+            i: u32,
+            reached: mpsc::Sender<()>,
+            popped: Arc<Mutex<mpsc::Receiver<()>>>,
+        }
+        impl serde::Serialize for Slow {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                if self.i == 2 {
+                    self.reached.send(()).unwrap();
+                    let popped = self.popped.lock().unwrap();
+                    popped
+                        .recv_timeout(Duration::from_secs(10))
+                        .expect("pops of written items still waited on the serializer");
+                }
+                s.serialize_u32(self.i)
+            }
+        }
+
+        let (reached, reached_rx) = mpsc::channel();
+        let (popped_tx, popped) = mpsc::channel();
+        let popped = Arc::new(Mutex::new(popped));
+        let q: Arc<HeQueue<_>> = Arc::new(
+            (0..4)
+                .map(|i| Slow {
+                    i,
+                    reached: reached.clone(),
+                    popped: popped.clone(),
+                })
+                .collect(),
+        );
+
+        let p = q.clone();
+        let popper = thread::spawn(move || {
+            reached_rx.recv().unwrap();
+            let front: Vec<_> = (0..2).map(|_| p.pop().unwrap().i).collect();
+            popped_tx.send(()).unwrap();
+            front
+        });
+        // in one pass, `bincode::serialize` would size it with a first one
+        let mut bytes = vec![];
+        bincode::serialize_into(&mut bytes, &*q).unwrap();
+        let items: Vec<u32> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(items, vec![0, 1, 2, 3]);
+        assert_eq!(popper.join().unwrap(), vec![0, 1]);
+        assert_eq!(q.len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let q = Arc::new(HeQueue::new());
+        for i in 0..pad / 2 {
+            q.push(i);
+        }
+
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            for i in pad / 2..pad {
+                p.push(i);
+                if i % 16 == 0 {
+                    thread::yield_now();
+                }
+            }
+        });
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let mut popped = 0;
+            while popped < pad {
+                if let Some(x) = c.pop() {
+                    assert_eq!(x, popped);
+                    popped += 1;
+                }
+                if popped % 16 == 0 {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut snapshots = 0;
+        while !producer.is_finished() || !consumer.is_finished() || snapshots == 0 {
+            let bytes = bincode::serialize(&*q).unwrap();
+            let items: Vec<usize> = bincode::deserialize::<HeQueue<_>>(&bytes)
+                .unwrap()
+                .drain()
+                .collect();
+            // a subsequence of what was pushed, in push order
+            assert!(items.windows(2).all(|w| w[0] < w[1]));
+            assert!(items.iter().all(|&x| x < pad));
+            snapshots += 1;
+        }
+
+        producer.join().unwrap();
+        consumer.join().unwrap();
+    }
+
+    #[test]
+    fn test_extend_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let q = Arc::new(HeQueue::new());
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let mut popped = vec![];
+            while popped.len() < 2 * pad {
+                if let Some(x) = c.pop() {
+                    popped.push(x);
+                }
+            }
+            popped
+        });
+
+        let mut p = &*q;
+        p.extend(0..pad);
+        p.extend(pad..2 * pad);
+
+        let popped = consumer.join().unwrap();
+        assert_eq!(popped, (0..2 * pad).collect::<Vec<_>>());
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_extend_from_slice() {
+        let items: Vec<u64> = (0..1000).map(|i| i * 7).collect();
+        let q = HeQueue::new();
+        q.push(u64::MAX);
+        q.extend_from_slice(&items);
+        q.extend_from_slice(&[]);
+        assert_eq!(q.size(), 1001);
+        assert_eq!(q.pop(), Some(u64::MAX));
+        assert_eq!(q.drain().collect::<Vec<_>>(), items);
+    }
+
+    #[test]
+    fn test_close() {
+        let q = HeQueue::new();
+        assert!(!q.is_closed());
+        q.push(1);
+        q.close();
+        assert!(q.is_closed());
+        assert_eq!(q.pop(), Some(1));
+    }
+
+    #[test]
+    fn test_close_refuses_pushes() {
+        let q = HeQueue::with_capacity(2);
+        assert_eq!(q.pop_result(), Err(PopError::Empty));
+        q.push(1);
+        assert_eq!(q.try_push(4), Ok(()));
+        assert_eq!(q.try_push(5), Err(PushError::Full(5)));
+        q.close();
+        assert_eq!(q.try_push(5), Err(PushError::Closed(5)));
+        q.push(1);
+        q.push_batch([4, 1]);
+        q.append([9].into_iter().collect());
+        assert_eq!(q.len(), 2);
+
+        // what was queued before the close still comes out
+        assert_eq!(q.pop_result(), Ok(1));
+        assert_eq!(q.pop_result(), Ok(4));
+        assert_eq!(q.pop_result(), Err(PopError::Closed));
+        assert_eq!(q.pop_result(), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn test_close_consumers_stop() {
+        let producers = 3;
+        let consumers = 3;
+        let pad = if cfg!(miri) { 32 } else { 10_000u64 };
+
+        let q = Arc::new(HeQueue::new());
+        let popped: Vec<_> = (0..consumers)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut sum = 0;
+                    loop {
+                        match q.pop_result() {
+                            Ok(i) => sum += i,
+                            Err(PopError::Empty) => thread::yield_now(),
+                            Err(PopError::Closed) => return sum,
+                        }
+                    }
+                })
+            })
+            .collect();
+        let pushed: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in (n * pad)..((n + 1) * pad) {
+                        q.push(i);
+                    }
+                })
+            })
+            .collect();
+        for p in pushed {
+            p.join().unwrap();
+        }
+        // every push has finished, so no consumer may stop before taking it
+        q.close();
+        let sum: u64 = popped.into_iter().map(|c| c.join().unwrap()).sum();
+        assert_eq!(sum, (0..producers * pad).sum());
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_close_racing_producers() {
+        let producers = 4;
+
+        let q = Arc::new(HeQueue::new());
+        let handles: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut accepted = 0u64;
+                    for i in 0u64.. {
+                        match q.try_push((n, i)) {
+                            Ok(()) => accepted += 1,
+                            Err(e) => {
+                                assert_eq!(e, PushError::Closed((n, i)));
+                                break;
+                            }
+                        }
+                    }
+                    accepted
+                })
+            })
+            .collect();
+
+        let mut popped = 0;
+        while popped < 1000 {
+            if q.pop_result().is_ok() {
+                popped += 1;
+            }
+        }
+        q.close();
+
+        // pushes that passed the check before the close may land after it,
+        // but `Closed` waits for them, so nothing accepted is left behind
+        // even while the producers are still running
+        loop {
+            match q.pop_result() {
+                Ok(_) => popped += 1,
+                Err(PopError::Empty) => thread::yield_now(),
+                Err(PopError::Closed) => break,
+            }
+        }
+        let accepted: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(popped, accepted);
+        assert!(q.is_closed());
+        assert_eq!(q.pop_result(), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn test_close_waits_for_pushes_in_flight() {
+        let q = HeQueue::new();
+        // a push past its check, stalled before linking
+        let token = q.start_push().unwrap();
+        q.close();
+        assert!(q.start_push().is_none());
+        assert_eq!(q.pop_result(), Err(PopError::Empty));
+
+        q.link(1, &epoch::pin());
+        drop(token);
+        assert_eq!(q.pop_result(), Ok(1));
+        assert_eq!(q.pop_result(), Err(PopError::Closed));
+
+        // a consumer parked on the end is woken by the last push in flight
+        let q = Arc::new(HeQueue::<i32>::new());
+        let token = q.start_push().unwrap();
+        q.close();
+        let c = q.clone();
+        let consumer = thread::spawn(move || c.pop_until_closed());
+        thread::sleep(Duration::from_millis(50));
+        assert!(!consumer.is_finished());
+        drop(token);
+        assert_eq!(consumer.join().unwrap(), None);
+    }
+
+    #[cfg(feature = "async")]
+    async fn next<T>(s: &mut crate::he_queue::HeStream<T>) -> Option<T> {
+        use std::pin::Pin;
+
+        use futures_core::Stream;
+
+        std::future::poll_fn(|cx| Pin::new(&mut *s).poll_next(cx)).await
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stream() {
+        let producers = 3;
+        let consumers = 4;
+        let pad = 2000;
+
+        let q = Arc::new(HeQueue::new());
+        let tasks: Vec<_> = (0..consumers)
+            .map(|_| {
+                let mut s = q.clone().stream();
+                tokio::spawn(async move {
+                    let mut got = vec![];
+                    while let Some(x) = next(&mut s).await {
+                        got.push(x);
+                    }
+                    got
+                })
+            })
+            .collect();
+
+        let handles: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in n * pad..(n + 1) * pad {
+                        q.push(i);
+                        if i % 64 == 0 {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        q.close();
+
+        let mut all = vec![];
+        for t in tasks {
+            all.extend(t.await.unwrap());
+        }
+        all.sort_unstable();
+        assert_eq!(all, (0..producers * pad).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream_closed() {
+        let q = Arc::new(HeQueue::new());
+        q.push(1);
+        q.close();
+        let mut s = q.clone().stream();
+        assert_eq!(next(&mut s).await, Some(1));
+        assert_eq!(next(&mut s).await, None);
+
+        // a stream parked on an empty queue ends on close
+        let q = Arc::new(HeQueue::<i32>::new());
+        let mut s = q.clone().stream();
+        let task = tokio::spawn(async move { next(&mut s).await });
+        tokio::task::yield_now().await;
+        q.close();
+        assert_eq!(task.await.unwrap(), None);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_stream_polled_idle() {
+        use std::{
+            pin::Pin,
+            task::{Context, Poll, Wake, Waker},
+        };
+
+        use futures_core::Stream;
+
+        struct Count(AtomicUsize);
+        impl Wake for Count {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let counted = || {
+            let count = Arc::new(Count(AtomicUsize::new(0)));
+            (Waker::from(count.clone()), count)
+        };
+
+        let q = Arc::new(HeQueue::new());
+        let mut s = q.clone().stream();
+        let (waker, count) = counted();
+        let mut cx = Context::from_waker(&waker);
+        // polled over and over on an idle queue, as in a `select!` loop,
+        // the stream stays registered once
+        for _ in 0..1000 {
+            assert_eq!(Pin::new(&mut s).poll_next(&mut cx), Poll::Pending);
+        }
+        q.push(1);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut s).poll_next(&mut cx), Poll::Ready(Some(1)));
+
+        // only the waker of the latest poll is woken
+        let (other, other_count) = counted();
+        assert_eq!(Pin::new(&mut s).poll_next(&mut cx), Poll::Pending);
+        let mut other_cx = Context::from_waker(&other);
+        assert_eq!(Pin::new(&mut s).poll_next(&mut other_cx), Poll::Pending);
+        q.push(2);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(other_count.0.load(Ordering::SeqCst), 1);
+
+        // and once woken, it registers again
+        assert_eq!(Pin::new(&mut s).poll_next(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(Pin::new(&mut s).poll_next(&mut cx), Poll::Pending);
+        q.push(3);
+        assert_eq!(count.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_pop_blocking() {
+        let q = Arc::new(HeQueue::new());
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let data: Instant = c.pop_blocking();
+            (data, Instant::now())
+        });
+
+        // give the consumer time to park
+        thread::sleep(Duration::from_millis(50));
+        q.push(Instant::now());
+        let (pushed, popped) = consumer.join().unwrap();
+        assert!(popped - pushed < Duration::from_secs(1));
+
+        q.push(Instant::now());
+        q.pop_blocking();
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_pop_deadline() {
+        let q = HeQueue::new();
+        let start = Instant::now();
+        assert_eq!(q.pop_deadline(start + Duration::from_millis(50)), None);
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(50));
+        assert!(waited < Duration::from_secs(5));
+
+        // an expired deadline still takes what's there
+        q.push(1);
+        assert_eq!(q.pop_deadline(start), Some(1));
+        assert_eq!(q.pop_deadline(start), None);
+    }
+
+    #[test]
+    fn test_pop_timeout() {
+        let q = HeQueue::new();
+        assert_eq!(q.pop_timeout(Duration::ZERO), None);
+        q.push(1);
+        assert_eq!(q.pop_timeout(Duration::ZERO), Some(1));
+
+        let start = Instant::now();
+        assert_eq!(q.pop_timeout(Duration::from_millis(50)), None);
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(50));
+        assert!(waited < Duration::from_secs(5));
+
+        q.push(2);
+        assert_eq!(q.pop_timeout(Duration::MAX), Some(2));
+    }
+
+    #[test]
+    fn test_pop_timeout_early_wake() {
+        let q = Arc::new(HeQueue::new());
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            let data = c.pop_timeout(Duration::from_secs(10));
+            (data, Instant::now())
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        let pushed = Instant::now();
+        q.push(1);
+        let (data, popped) = consumer.join().unwrap();
+        assert_eq!(data, Some(1));
+        assert!(popped - pushed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_pop_timeout_racing_consumer() {
+        let rounds = if cfg!(miri) { 8 } else { 200 };
+
+        let q = Arc::new(HeQueue::new());
+        let (tx, rx) = mpsc::channel();
+        let c = q.clone();
+        let untimed = thread::spawn(move || loop {
+            match c.pop_blocking() {
+                None => break,
+                Some(i) => tx.send(i).unwrap(),
+            }
+        });
+
+        let mut got = vec![];
+        for i in 0..rounds {
+            let p = q.clone();
+            // pushes land right around the timed consumer's deadline, or
+            // on its re-check pop, with a second one to wake someone for
+            let producer = thread::spawn(move || {
+                thread::sleep(Duration::from_micros(500));
+                p.push(Some(2 * i));
+                p.push(Some(2 * i + 1));
+            });
+            let timed = q.pop_timeout(Duration::from_micros(500 + i % 2 * 500));
+            producer.join().unwrap();
+            // the untimed consumer must get whatever the timed one did
+            // not, whichever way the timed one left
+            let mut round = vec![];
+            round.extend(timed.map(Option::unwrap));
+            while round.len() < 2 {
+                round.push(
+                    rx.recv_timeout(Duration::from_secs(5))
+                        .expect("the untimed consumer slept through a queued item"),
+                );
+            }
+            got.extend(round);
+        }
+        q.push(None);
+        untimed.join().unwrap();
+
+        // every push reached exactly one of the two consumers
+        got.sort_unstable();
+        assert_eq!(got, (0..2 * rounds).collect::<Vec<_>>());
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_pop_blocking_recheck_wins() {
+        let rounds = if cfg!(miri) { 4 } else { 1000 };
+
+        let q = Arc::new(HeQueue::new());
+        for r in 0..rounds {
+            let (tx, rx) = mpsc::channel();
+            let consumer = || {
+                let q = q.clone();
+                let tx = tx.clone();
+                thread::spawn(move || tx.send(q.pop_blocking()).unwrap())
+            };
+            // one consumer parked, the other still on its way in, so the
+            // first push can land on its re-check pop while the second
+            // push wakes it instead of the parked one
+            let parked = consumer();
+            while q.waiters.waiting() == 0 {
+                thread::yield_now();
+            }
+            let late = consumer();
+            thread::sleep(Duration::from_micros(r % 200));
+            q.push(2 * r);
+            q.push(2 * r + 1);
+
+            let mut got: Vec<_> = (0..2)
+                .map(|_| {
+                    rx.recv_timeout(Duration::from_secs(5))
+                        .expect("a consumer slept through a queued item")
+                })
+                .collect();
+            got.sort_unstable();
+            assert_eq!(got, [2 * r, 2 * r + 1]);
+            parked.join().unwrap();
+            late.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pop_blocking_burst() {
+        let waiters = 8;
+
+        let q = Arc::new(HeQueue::new());
+        let (tx, rx) = mpsc::channel();
+        let handles: Vec<_> = (0..waiters)
+            .map(|_| {
+                let q = q.clone();
+                let tx = tx.clone();
+                thread::spawn(move || tx.send(q.pop_blocking()).unwrap())
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(50));
+        for i in 0..waiters {
+            q.push(i);
+        }
+
+        // a lost wakeup leaves a waiter parked with its item still queued
+        let mut got: Vec<_> = (0..waiters)
+            .map(|_| rx.recv_timeout(Duration::from_secs(10)).unwrap())
+            .collect();
+        got.sort_unstable();
+        assert_eq!(got, (0..waiters).collect::<Vec<_>>());
+        assert!(q.is_empty());
+        for h in handles {
+            h.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let q: HeQueue<_> = [1, 1].into_iter().collect();
+        q.append([4, 5].into_iter().collect());
+        q.append(HeQueue::new());
+        q.push(1);
+        let staging = HeQueue::new();
+        staging.push(4);
+        staging.push(0);
+        staging.pop();
+        q.append(staging);
+        assert_eq!(q.size(), 6);
+        assert_eq!(q.drain().collect::<Vec<_>>(), vec![1, 1, 4, 5, 1, 0]);
+
+        // onto an empty queue
+        let q = HeQueue::new();
+        q.append((0..3).map(|i| i.to_string()).collect());
+        assert_eq!(q.drain().collect::<Vec<_>>(), vec!["0", "1", "2"]);
+    }
+
+    #[test]
+    fn test_append_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+        let run = if cfg!(miri) { 16 } else { 1000usize };
+
+        let q = Arc::new(HeQueue::new());
+        let stop = Arc::new(AtomicI32::new(0));
+
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..pad {
+                p.push(i);
+            }
+        });
+        let c = q.clone();
+        let flag = stop.clone();
+        let consumer = thread::spawn(move || {
+            let mut popped = vec![];
+            while flag.load(Ordering::SeqCst) == 0 {
+                popped.extend(c.pop());
+            }
+            popped
+        });
+
+        let staging: HeQueue<_> = (pad..pad + run).collect();
+        q.append(staging);
+
+        producer.join().unwrap();
+        stop.store(1, Ordering::SeqCst);
+        let mut popped = consumer.join().unwrap();
+        popped.extend(q.drain());
+
+        let mut sorted = popped.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..pad + run).collect::<Vec<_>>());
+
+        // a single consumer pops in list order, so the run must show up whole
+        let start = popped.iter().position(|&x| x >= pad).unwrap();
+        assert_eq!(
+            popped[start..start + run],
+            (pad..pad + run).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_addr_emptied() {
+        let q = HeQueue::new();
+        assert_eq!(q.head_addr(), q.tail_addr());
+        q.push(1);
+        assert_ne!(q.head_addr(), q.tail_addr());
+        q.pop();
+        assert_eq!(q.head_addr(), q.tail_addr());
+
+        // strict tail: after a concurrent run, the tail is back on the sentinel
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+        let q = Arc::new(HeQueue::new());
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push(i);
+                        q.pop();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        while q.pop().is_some() {}
+        assert_eq!(q.head_addr(), q.tail_addr());
+    }
+
+    #[test]
+    fn test_into_vec() {
+        let pad = if cfg!(miri) { 100 } else { 1_000_000 };
+
+        let q: HeQueue<_> = (0..pad).collect();
+        q.pop();
+        q.push(pad);
+        let v = q.into_vec();
+        assert_eq!(v, (1..=pad).collect::<Vec<_>>());
+
+        let q = HeQueue::new();
+        q.push_batch([1, 1, 4]);
+        q.push(5);
+        let mut iter = q.into_iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.collect::<Vec<_>>(), vec![1, 4, 5]);
+
+        assert!(HeQueue::<i32>::new().into_vec().is_empty());
+    }
+
+    #[test]
+    fn test_into_iter_drop() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let q = HeQueue::new();
+        for _ in 0..10 {
+            q.push(Counted);
+        }
+        q.pop();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+
+        let mut iter = q.into_iter();
+        drop(iter.next());
+        drop(iter.next());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+        // the remaining items are dropped exactly once with the iterator
+        drop(iter);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_structurally_empty() {
+        let q = HeQueue::new();
+        assert!(q.is_structurally_empty());
+        q.push(1);
+        assert!(!q.is_structurally_empty());
+        q.pop();
+        assert!(q.is_structurally_empty());
+
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+        let q = Arc::new(HeQueue::new());
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..pad {
+                p.push(i);
+            }
+        });
+        let mut popped = 0;
+        while popped < pad {
+            // mid-flight, the counter and the list may disagree either way
+            if q.pop().is_some() {
+                popped += 1;
+            }
+        }
+        producer.join().unwrap();
+
+        // fully drained, both agree
+        assert!(q.is_empty());
+        assert!(q.is_structurally_empty());
+    }
+
+    #[test]
+    fn test_spsc() {
+        let q = HeQueue::new();
+        q.push_batch([1, 1]);
+        let (mut tx, mut rx) = q.split_spsc();
+        tx.push(4);
+        assert_eq!(rx.pop(), Some(1));
+        assert_eq!(rx.pop(), Some(1));
+        assert_eq!(rx.pop(), Some(4));
+        assert_eq!(rx.pop(), None);
+        assert!(rx.is_empty());
+        tx.push(5);
+        assert!(!rx.is_empty());
+        assert_eq!(rx.pop(), Some(5));
+
+        let pad = if cfg!(miri) { 64 } else { 100_000usize };
+        let (mut tx, mut rx) = HeQueue::new().split_spsc();
+        let producer = thread::spawn(move || {
+            for i in 0..pad {
+                tx.push(i);
+            }
+        });
+        let mut next = 0;
+        while next < pad {
+            if let Some(i) = rx.pop() {
+                assert_eq!(i, next);
+                next += 1;
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(rx.pop(), None);
+    }
+
+    #[test]
+    fn test_spsc_drop() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let (mut tx, mut rx) = HeQueue::new().split_spsc();
+        for _ in 0..10 {
+            tx.push(Counted);
+        }
+        drop(rx.pop());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        // everything still queued goes with the receiver
+        drop(rx);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+        // and anything pushed after that with the sender
+        tx.push(Counted);
+        drop(tx);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 11);
+    }
+
+    #[test]
+    fn test_with_guard() {
+        let q = HeQueue::new();
+        let guard = &epoch::pin();
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push_with(i, guard);
+        }
+        assert_eq!(q.pop_with(guard), Some(1));
+        assert_eq!(q.pop(), Some(1));
+        q.push(9);
+        let mut rest = vec![];
+        while let Some(i) = q.pop_with(guard) {
+            rest.push(i);
+        }
+        assert_eq!(rest, vec![4, 5, 1, 4, 9]);
+    }
+
+    #[test]
+    #[should_panic = "global collector"]
+    fn test_with_foreign_guard() {
+        let q = HeQueue::new();
+        q.push(1);
+        let collector = epoch::Collector::new();
+        let handle = collector.register();
+        q.pop_with(&handle.pin());
+    }
+
+    #[test]
+    #[should_panic = "global collector"]
+    fn test_with_unprotected() {
+        let q = HeQueue::new();
+        q.push_with(1, unsafe { epoch::unprotected() });
+    }
+
+    #[test]
+    fn test_with_guard_reclaims() {
+        let rounds = if cfg!(miri) { 8 } else { 100_000 };
+        let repin = 128;
+
+        let q = HeQueue::new();
+        let freed = Arc::new(AtomicUsize::new(0));
+
+        // garbage retired under a guard that is never repinned stays put
+        let mut guard = epoch::pin();
+        let f = freed.clone();
+        guard.defer(move || f.store(1, Ordering::SeqCst));
+        for i in 0..repin * 8 {
+            q.push_with(i, &guard);
+            q.pop_with(&guard);
+        }
+        guard.flush();
+        assert_eq!(freed.load(Ordering::SeqCst), 0);
+
+        // repinning regularly lets the epoch move on and collect it
+        for i in 0..rounds {
+            q.push_with(i, &guard);
+            q.pop_with(&guard);
+            if i % repin == 0 {
+                guard.repin();
+                guard.flush();
+            }
+            if freed.load(Ordering::SeqCst) == 1 {
+                break;
             }
-            flag3.fetch_sub(1, Ordering::SeqCst);
-        });
+        }
+        assert_eq!(freed.load(Ordering::SeqCst), 1);
+    }
 
-        let mut sum = 0;
-        while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
-            if let Some(num) = c.pop() {
-                sum += num;
+    #[test]
+    fn test_approx_memory_bytes() {
+        let pad = if cfg!(miri) { 64 } else { 10_000u64 };
+        let node = mem::size_of::<Node<u64>>();
+
+        let q = HeQueue::new();
+        let baseline = q.approx_memory_bytes();
+        assert_eq!(baseline, mem::size_of::<HeQueue<u64>>() + node);
+
+        for i in 0..pad {
+            q.push(i);
+        }
+        assert_eq!(q.approx_memory_bytes(), baseline + pad as usize * node);
+        while q.pop().is_some() {}
+
+        // popped nodes linger until the collector gets to them, other
+        // tests pinning meanwhile may hold the epoch back for a while
+        for _ in 0..10_000 {
+            if q.approx_memory_bytes() == baseline {
+                break;
             }
+            epoch::pin().flush();
+            thread::yield_now();
         }
+        assert_eq!(q.approx_memory_bytes(), baseline);
+    }
 
-        t1.join().unwrap();
-        t2.join().unwrap();
-        t3.join().unwrap();
-        assert_eq!(sum, (0..(3 * pad)).sum());
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_retired_nodes() {
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+        let threads = 4;
+
+        let q = Arc::new(HeQueue::new());
+        for i in 0..pad * threads {
+            q.push(i);
+        }
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut popped = 0;
+                    while q.pop().is_some() {
+                        // an underflow would wrap around to a huge count
+                        assert!(q.retired_nodes() <= pad * threads);
+                        popped += 1;
+                        if popped % 64 == 0 {
+                            epoch::pin().flush();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        for _ in 0..10_000 {
+            if q.retired_nodes() == 0 {
+                break;
+            }
+            epoch::pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(q.retired_nodes(), 0);
+
+        // the count outlives the queue in its destructors, dropping it
+        // with garbage still queued must be fine
+        let q = HeQueue::new();
+        q.push(1);
+        q.pop();
+        drop(q);
+        epoch::pin().flush();
     }
 
     #[test]
-    fn test_mpmc() {
-        let pad = 10_0000u128;
+    fn test_drain() {
+        let q = HeQueue::new();
+        assert_eq!(q.drain().next(), None);
+
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push(i);
+        }
+        assert_eq!(q.drain().size_hint(), (6, None));
+        assert_eq!(q.drain().collect::<Vec<_>>(), vec![1, 1, 4, 5, 1, 4]);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_drain_settles_len() {
+        let pad = if cfg!(miri) { 500 } else { 50_000 };
+
+        let q: HeQueue<_> = (0..pad).collect();
+        let mut drain = q.drain();
+        let head: Vec<_> = drain.by_ref().take(10).collect();
+        assert_eq!(head, (0..10).collect::<Vec<_>>());
+        // `len` may lag mid-drain, never the other way
+        assert!(q.size() >= pad - 10);
+        assert_eq!(drain.size_hint(), (pad - 10, None));
+        drop(drain);
+        assert_eq!(q.size(), pad - 10);
+
+        assert_eq!(q.drain().count(), pad - 10);
+        assert_eq!(q.size(), 0);
+        assert!(q.is_empty());
+
+        // settled when it runs dry too, while the drain is still held
+        let q: HeQueue<_> = (0..pad).collect();
+        let mut drain = q.drain();
+        assert_eq!(drain.by_ref().count(), pad);
+        assert_eq!(q.size(), 0);
+        q.push(1);
+        assert_eq!(drain.next(), Some(1));
+        drop(drain);
+        assert_eq!(q.size(), 0);
+
+        // as documented, a held drain stopped partway keeps a bounded
+        // queue looking full until it is dropped
+        let q = HeQueue::with_capacity(4);
+        for i in 0..4 {
+            q.try_push(i).unwrap();
+        }
+        let mut drain = q.drain();
+        assert_eq!(drain.next(), Some(0));
+        assert_eq!(q.try_push(4), Err(PushError::Full(4)));
+        drop(drain);
+        assert_eq!(q.try_push(4), Ok(()));
+    }
+
+    #[test]
+    fn test_pop_all() {
+        let q: HeQueue<_> = (0..100).collect();
+        assert_eq!(q.pop_all().size_hint(), (100, None));
+        assert_eq!(
+            q.pop_all().take(10).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+        // dropping it early left the rest alone
+        assert_eq!(q.len(), 90);
+        assert_eq!(q.pop(), Some(10));
+
+        let odd: Vec<_> = q
+            .pop_all()
+            .take_while(|&i| i < 20)
+            .filter(|i| i % 2 == 1)
+            .collect();
+        assert_eq!(odd, vec![11, 13, 15, 17, 19]);
+        // `take_while` popped the 20 to look at it
+        assert_eq!(q.pop(), Some(21));
+    }
+
+    #[test]
+    fn test_pop_all_concurrent() {
+        let producers = 3;
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        let flag = Arc::new(AtomicI32::new(producers as i32));
+        let q = Arc::new(HeQueue::new());
+        let pushed: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push((n, i));
+                    }
+                    flag.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        let popped: Vec<_> = (0..2)
+            .map(|_| {
+                let q = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let mut log = vec![];
+                    while flag.load(Ordering::SeqCst) != 0 || !q.is_empty() {
+                        // short bites, abandoning the iterator each time
+                        log.extend(q.pop_all().take(7));
+                    }
+                    log
+                })
+            })
+            .collect();
+
+        for p in pushed {
+            p.join().unwrap();
+        }
+        let logs: Vec<_> = popped.into_iter().map(|c| c.join().unwrap()).collect();
+        check_logs(producers, pad, &logs);
+    }
+
+    #[test]
+    fn test_concurrent_drain() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000u128 };
 
         let flag = Arc::new(AtomicI32::new(3));
-        let flag_c = flag.clone();
-        let flag1 = flag.clone();
-        let flag2 = flag.clone();
-        let flag3 = flag.clone();
+        let q = Arc::new(HeQueue::new());
+
+        let producers: Vec<_> = (0..3)
+            .map(|n| {
+                let p = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    for i in (n * pad)..((n + 1) * pad) {
+                        p.push(i);
+                    }
+                    flag.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let c = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let mut got = vec![];
+                    while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+                        got.extend(c.drain());
+                    }
+                    got
+                })
+            })
+            .collect();
+
+        for p in producers {
+            p.join().unwrap();
+        }
+        let mut got: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        got.sort_unstable();
+        assert_eq!(got, (0..(3 * pad)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_push_batch() {
+        let q = HeQueue::new();
+        q.push_batch(Vec::new());
+        assert!(q.is_empty());
+
+        q.push(1);
+        q.push_batch(vec![1, 4, 5]);
+        q.push_batch([1]);
+        q.push(4);
+        assert_eq!(q.size(), 6);
+        assert_eq!(q.drain().collect::<Vec<_>>(), vec![1, 1, 4, 5, 1, 4]);
+    }
+
+    #[test]
+    fn test_push_batch_contiguous() {
+        let batch = 16;
+        let pad = if cfg!(miri) { 64 } else { 1000 };
 
         let p1 = Arc::new(HeQueue::new());
         let p2 = p1.clone();
-        let p3 = p1.clone();
-        let c1 = p1.clone();
-        let c2 = p1.clone();
+        let c = p1.clone();
 
-        let producer1 = thread::spawn(move || {
-            for i in 0..pad {
-                p1.push(i);
+        let t1 = thread::spawn(move || {
+            for n in 0..pad {
+                p1.push_batch((0..batch).map(|i| (1, n * batch + i)));
             }
-            flag1.fetch_sub(1, Ordering::SeqCst);
         });
-        let producer2 = thread::spawn(move || {
-            for i in pad..(2 * pad) {
-                p2.push(i);
+        let t2 = thread::spawn(move || {
+            for i in 0..(pad * batch) {
+                p2.push((2, i));
             }
-            flag2.fetch_sub(1, Ordering::SeqCst);
         });
-        let producer3 = thread::spawn(move || {
-            for i in (2 * pad)..(3 * pad) {
-                p3.push(i);
+        t1.join().unwrap();
+        t2.join().unwrap();
+
+        // every batch must come out in one piece
+        let got: Vec<_> = c.drain().collect();
+        let mut i = 0;
+        while i < got.len() {
+            if got[i].0 == 1 {
+                let start = got[i].1;
+                assert_eq!(start % batch, 0);
+                for (k, item) in got[i..(i + batch)].iter().enumerate() {
+                    assert_eq!(*item, (1, start + k));
+                }
+                i += batch;
+            } else {
+                i += 1;
             }
-            flag3.fetch_sub(1, Ordering::SeqCst);
-        });
+        }
+    }
 
-        let consumer = thread::spawn(move || {
-            let mut sum = 0;
-            while flag_c.load(Ordering::SeqCst) != 0 || !c2.is_empty() {
-                if let Some(num) = c2.pop() {
-                    sum += num;
+    #[test]
+    fn test_mpmc_push_batch() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000u128 };
+
+        let flag = Arc::new(AtomicI32::new(3));
+        let q = Arc::new(HeQueue::new());
+
+        let producers: Vec<_> = (0..3)
+            .map(|n| {
+                let p = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let range = (n * pad)..((n + 1) * pad);
+                    if n == 0 {
+                        for i in range {
+                            p.push(i);
+                        }
+                    } else {
+                        // batches of varying size, including a ragged last one
+                        let items: Vec<_> = range.collect();
+                        for chunk in items.chunks(n as usize * 13) {
+                            p.push_batch(chunk.iter().copied());
+                        }
+                    }
+                    flag.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let c = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let mut sum = 0;
+                    while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+                        if let Some(num) = c.pop() {
+                            sum += num;
+                        }
+                    }
+                    sum
+                })
+            })
+            .collect();
+
+        for p in producers {
+            p.join().unwrap();
+        }
+        let sum: u128 = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+        assert_eq!(sum, (0..(3 * pad)).sum());
+    }
+
+    #[test]
+    fn test_zst() {
+        let pad = if cfg!(miri) { 64 } else { 100_000 };
+
+        let q = HeQueue::new();
+        for _ in 0..pad {
+            q.push(());
+        }
+        assert_eq!(q.size(), pad);
+        for _ in 0..(pad / 2) {
+            assert_eq!(q.pop(), Some(()));
+        }
+        assert_eq!(q.size(), pad / 2);
+        while q.pop().is_some() {}
+        assert!(q.is_empty());
+        assert_eq!(q.pop(), None);
+
+        // a zero-sized payload with drop glue, left in the queue on drop
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let q = HeQueue::new();
+        for _ in 0..pad {
+            q.push(Token);
+        }
+        for _ in 0..(pad / 2) {
+            drop(q.pop());
+        }
+        drop(q);
+        assert_eq!(DROPS.load(Ordering::SeqCst), pad);
+    }
+
+    #[test]
+    fn test_pop_into() {
+        let q = HeQueue::new();
+        let mut out = vec![];
+        assert_eq!(q.pop_into(&mut out, 4), 0);
+
+        q.push_batch([1, 1, 4, 5, 1, 4]);
+        assert_eq!(q.pop_into(&mut out, 0), 0);
+        assert_eq!(q.pop_into(&mut out, 4), 4);
+        assert_eq!(out, vec![1, 1, 4, 5]);
+        assert_eq!(q.size(), 2);
+
+        // stops early on empty
+        assert_eq!(q.pop_into(&mut out, 4), 2);
+        assert_eq!(out, vec![1, 1, 4, 5, 1, 4]);
+        assert!(q.is_empty());
+
+        q.push_batch([1, 9, 1, 9]);
+        assert_eq!(q.pop_batch(3), vec![1, 9, 1]);
+        assert_eq!(q.size(), 1);
+    }
+
+    #[test]
+    fn test_mpmc_pop_into() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000u128 };
+
+        let flag = Arc::new(AtomicI32::new(3));
+        let q = Arc::new(HeQueue::new());
+
+        let producers: Vec<_> = (0..3)
+            .map(|n| {
+                let p = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    for i in (n * pad)..((n + 1) * pad) {
+                        p.push(i);
+                    }
+                    flag.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        let c = q.clone();
+        let flag_c = flag.clone();
+        let batch_consumer = thread::spawn(move || {
+            let mut got = vec![];
+            while flag_c.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+                let mut chunk = vec![];
+                c.pop_into(&mut chunk, 32);
+                // each producer's items stay in order within a chunk
+                for n in 0..3 {
+                    let own: Vec<_> = chunk.iter().filter(|&&i| i / pad == n).collect();
+                    assert!(own.windows(2).all(|w| w[0] < w[1]));
                 }
+                got.extend(chunk);
             }
-            sum
+            got
         });
 
-        let mut sum = 0;
-        while flag.load(Ordering::SeqCst) != 0 || !c1.is_empty() {
-            if let Some(num) = c1.pop() {
-                sum += num;
+        let mut got = vec![];
+        while flag.load(Ordering::SeqCst) != 0 || !q.is_empty() {
+            if let Some(num) = q.pop() {
+                got.push(num);
             }
         }
 
-        producer1.join().unwrap();
-        producer2.join().unwrap();
-        producer3.join().unwrap();
+        for p in producers {
+            p.join().unwrap();
+        }
+        got.extend(batch_consumer.join().unwrap());
+        got.sort_unstable();
+        assert_eq!(got, (0..(3 * pad)).collect::<Vec<_>>());
+        assert_eq!(q.size(), 0);
+    }
 
-        let s = consumer.join().unwrap();
-        sum += s;
-        assert_eq!(sum, (0..(3 * pad)).sum());
+    #[test]
+    fn test_len_quiescent() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000u128 };
+
+        let flag = Arc::new(AtomicI32::new(3));
+        let q = Arc::new(HeQueue::new());
+
+        let producers: Vec<_> = (0..3)
+            .map(|n| {
+                let p = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let range = (n * pad)..((n + 1) * pad);
+                    if n == 0 {
+                        p.push_batch(range);
+                    } else {
+                        for i in range {
+                            p.push(i);
+                        }
+                    }
+                    flag.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|n| {
+                let c = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let mut got = vec![];
+                    while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+                        if n == 0 {
+                            got.extend(c.pop());
+                        } else {
+                            c.pop_into(&mut got, 7);
+                        }
+                    }
+                    got.len()
+                })
+            })
+            .collect();
+
+        for p in producers {
+            p.join().unwrap();
+        }
+        let popped: usize = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+        assert_eq!(popped, 3 * pad as usize);
+        // exact once everyone is done
+        assert_eq!(q.size(), 0);
+        assert!(q.is_empty());
+        assert_eq!(q.pop(), None);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_stats() {
+        let producers = 4;
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        let q = Arc::new(HeQueue::new());
+        q.push_batch([1, 1, 4]);
+        q.pop_batch(2);
+        let stats = q.stats();
+        assert_eq!((stats.pushes, stats.pops), (3, 2));
+
+        // retries need a producer to lose a race, which on a busy or single
+        // core machine only happens when it is preempted at the right moment
+        let mut rounds = 0;
+        while q.stats().cas_retries == 0 && rounds < 20 {
+            let handles: Vec<_> = (0..producers)
+                .map(|_| {
+                    let q = q.clone();
+                    thread::spawn(move || {
+                        for i in 0..pad {
+                            q.push(i);
+                            q.pop();
+                        }
+                    })
+                })
+                .collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+            rounds += 1;
+        }
+
+        let stats = q.stats();
+        assert!(stats.cas_retries > 0);
+        assert_eq!(stats.pushes, 3 + rounds * producers * pad);
+        assert_eq!(stats.pushes - stats.pops, q.len());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_contention_stats() {
+        let threads = 4;
+        let pad = if cfg!(miri) { 64 } else { 10_000usize };
+
+        // uncontended, every link succeeds first time and no pop retries
+        let q = Arc::new(HeQueue::new());
+        for i in 0..pad {
+            q.push(i);
+        }
+        q.push_batch(0..pad);
+        while q.pop().is_some() {}
+        let stats = q.stats().contention;
+        assert_eq!(stats.push_attempts, pad + 1);
+        assert_eq!(stats.push_cas_failures, 0);
+        assert_eq!(stats.pop_retries, 0);
+        assert_eq!(stats.push_failure_rate(), 0.0);
+
+        q.reset_stats();
+        assert_eq!(q.stats().contention, HeStats::default());
+
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push(i);
+                        q.pop();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        // whether any CAS failed depends on scheduling,
+        // but each push succeeds exactly once
+        let stats = q.stats().contention;
+        assert_eq!(stats.push_attempts - stats.push_cas_failures, threads * pad);
+        assert!(stats.push_failure_rate() < 1.0);
+    }
+
+    #[test]
+    fn test_backoff_policies() {
+        let pad = if cfg!(miri) { 64 } else { 10_0000u128 };
+
+        for policy in [BackoffPolicy::Spin, BackoffPolicy::SpinThenYield] {
+            let flag = Arc::new(AtomicI32::new(3));
+            let q = Arc::new(HeQueue::with_backoff(policy));
+
+            let producers: Vec<_> = (0..3)
+                .map(|n| {
+                    let p = q.clone();
+                    let flag = flag.clone();
+                    thread::spawn(move || {
+                        for i in (n * pad)..((n + 1) * pad) {
+                            p.push(i);
+                        }
+                        flag.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    let c = q.clone();
+                    let flag = flag.clone();
+                    thread::spawn(move || {
+                        let mut sum = 0;
+                        while flag.load(Ordering::SeqCst) != 0 || !c.is_empty() {
+                            if let Some(num) = c.pop() {
+                                sum += num;
+                            }
+                        }
+                        sum
+                    })
+                })
+                .collect();
+
+            for p in producers {
+                p.join().unwrap();
+            }
+            let sum: u128 = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+            assert_eq!(sum, (0..(3 * pad)).sum());
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(if cfg!(miri) { 4 } else { 256 }))]
+
+        #[test]
+        fn prop_matches_vec_deque(ops in ops(200)) {
+            let q = HeQueue::new();
+            prop_assert_eq!(replay(&q, &ops), replay_model(&ops));
+        }
+
+        #[test]
+        fn prop_concurrent_log(
+            producers in 1..4usize,
+            consumers in 1..3usize,
+            per_producer in 0..200usize,
+        ) {
+            let q = Arc::new(HeQueue::new());
+            let flag = Arc::new(AtomicI32::new(producers as i32));
+
+            let ps: Vec<_> = (0..producers)
+                .map(|p| {
+                    let q = q.clone();
+                    let flag = flag.clone();
+                    thread::spawn(move || {
+                        for seq in 0..per_producer {
+                            q.push((p, seq));
+                        }
+                        flag.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+            let cs: Vec<_> = (0..consumers)
+                .map(|_| {
+                    let q = q.clone();
+                    let flag = flag.clone();
+                    thread::spawn(move || {
+                        let mut log = vec![];
+                        while flag.load(Ordering::SeqCst) != 0 || !q.is_empty() {
+                            log.extend(q.pop());
+                        }
+                        log
+                    })
+                })
+                .collect();
+
+            for p in ps {
+                p.join().unwrap();
+            }
+            let logs: Vec<_> = cs.into_iter().map(|c| c.join().unwrap()).collect();
+            check_logs(producers, per_producer, &logs);
+        }
+    }
+
+    #[test]
+    fn test_steal_half() {
+        let src = HeQueue::new();
+        let dst = HeQueue::new();
+        assert_eq!(src.steal_half(&dst), 0);
+
+        src.push_batch(1..=5);
+        dst.push(0);
+        assert_eq!(src.steal_half(&dst), 3);
+        assert_eq!(src.drain().collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(dst.drain().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_steal_half_balancing() {
+        let workers = 4;
+        let pad = if cfg!(miri) { 64 } else { 10_0000usize };
+
+        let queues: Arc<Vec<HeQueue<usize>>> =
+            Arc::new((0..workers).map(|_| HeQueue::new()).collect());
+        // all the work starts out on the first worker
+        queues[0].push_batch(0..pad);
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..workers)
+            .map(|w| {
+                let queues = queues.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut processed = vec![];
+                    while done.load(Ordering::SeqCst) < pad {
+                        if let Some(item) = queues[w].pop() {
+                            processed.push(item);
+                            done.fetch_add(1, Ordering::SeqCst);
+                            continue;
+                        }
+                        // idle, steal from the others in turn
+                        for v in (1..workers).map(|i| (w + i) % workers) {
+                            if queues[v].steal_half(&queues[w]) > 0 {
+                                break;
+                            }
+                        }
+                    }
+                    processed
+                })
+            })
+            .collect();
+
+        let mut processed: Vec<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        processed.sort_unstable();
+        assert_eq!(processed, (0..pad).collect::<Vec<_>>());
+        assert!(queues.iter().all(|q| q.is_empty()));
+    }
+
+    #[test]
+    fn test_try_push() {
+        let q = HeQueue::with_capacity(2);
+        assert_eq!(q.try_push(String::from("1")), Ok(()));
+        assert_eq!(q.try_push(String::from("4")), Ok(()));
+        let rejected = q.try_push(String::from("5")).unwrap_err();
+        assert_eq!(rejected.into_inner(), "5");
+        assert_eq!(q.len(), 2);
+
+        assert_eq!(q.pop().as_deref(), Some("1"));
+        assert_eq!(q.try_push(String::from("5")), Ok(()));
+
+        // unbounded queues never reject
+        let q = HeQueue::new();
+        for i in 0..1000 {
+            assert_eq!(q.try_push(i), Ok(()));
+        }
+    }
+
+    // the bounded-only counter follows every way in and out of the list
+    #[cfg(feature = "he-no-len")]
+    #[test]
+    fn test_bounded_len() {
+        assert!(HeQueue::<i32>::new().bounded_len.is_none());
+
+        let mut q = HeQueue::with_capacity(8);
+        let counted = |q: &HeQueue<i32>| {
+            assert_eq!(q.len(), q.count_nodes());
+            q.len()
+        };
+        for i in 0..6 {
+            q.try_push(i).unwrap();
+        }
+        q.pop();
+        q.push_batch([6, 7]);
+        q.append(HeQueue::from_iter([8, 9]));
+        assert_eq!(counted(&q), 9);
+        assert_eq!(q.try_push(10), Err(PushError::Full(10)));
+
+        q.retain(|x| x % 2 == 0);
+        assert_eq!(counted(&q), 4);
+        let c = q.clone();
+        assert_eq!(counted(&c), 4);
+        assert_eq!(c.try_push(10), Ok(()));
+        {
+            let mut drain = q.drain();
+            drain.next();
+            assert_eq!(drain.size_hint().0, 3);
+        }
+        assert_eq!(counted(&q), 3);
+        q.drain().for_each(drop);
+        assert_eq!(counted(&q), 0);
+    }
+
+    #[test]
+    fn test_remaining_capacity() {
+        let q = HeQueue::with_capacity(4);
+        assert_eq!(q.capacity(), Some(4));
+        assert_eq!(q.remaining_capacity(), Some(4));
+        q.try_push(1).unwrap();
+        q.try_push(4).unwrap();
+        assert_eq!(q.remaining_capacity(), Some(2));
+        // `push` may overshoot the bound, which saturates
+        for i in 0..3 {
+            q.push(i);
+        }
+        assert_eq!(q.remaining_capacity(), Some(0));
+
+        let q = HeQueue::<i32>::new();
+        assert_eq!(q.capacity(), None);
+        assert_eq!(q.remaining_capacity(), None);
+    }
+
+    #[test]
+    fn test_try_push_returns_same_item() {
+        let q = HeQueue::with_capacity(1);
+        q.try_push(Box::new(1)).unwrap();
+        let item = Box::new(4);
+        let addr: *const i32 = &*item;
+        // the very allocation comes back, not a copy
+        let back = q.try_push(item).unwrap_err().into_inner();
+        assert!(std::ptr::eq(addr, &*back));
+
+        q.close();
+        let item = Box::new(5);
+        let addr: *const i32 = &*item;
+        match q.try_push(item) {
+            Err(PushError::Closed(back)) => assert!(std::ptr::eq(addr, &*back)),
+            other => panic!("expected Closed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_try_push_full() {
+        let cap = 16;
+        let producers = 4;
+        let rounds = if cfg!(miri) { 64 } else { 10_0000usize };
+
+        let q = Arc::new(HeQueue::with_capacity(cap));
+        let flag = Arc::new(AtomicI32::new(producers as i32));
+
+        let handles: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    let mut accepted = 0;
+                    for i in 0..rounds {
+                        let item = format!("{}-{}", n, i);
+                        match q.try_push(item.clone()) {
+                            Ok(()) => accepted += 1,
+                            // handed back intact
+                            Err(e) => assert_eq!(e, PushError::Full(item)),
+                        }
+                    }
+                    flag.fetch_sub(1, Ordering::SeqCst);
+                    accepted
+                })
+            })
+            .collect();
+
+        let mut popped = 0;
+        while flag.load(Ordering::SeqCst) != 0 || !q.is_empty() {
+            assert!(q.len() <= cap + producers);
+            // drain slowly so the producers mostly see a full queue
+            if popped % 4 == 0 {
+                thread::yield_now();
+            }
+            if q.pop().is_some() {
+                popped += 1;
+            }
+        }
+
+        let accepted: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(accepted, popped);
     }
 }
@@ -1,4 +1,37 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// only `HeQueue` and what it is built from are left without `std`
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod channel;
+#[cfg(feature = "std")]
 pub mod crs_queue;
+#[cfg(feature = "dwcas")]
+mod dwcas;
+#[cfg(feature = "std")]
+mod hazard;
+#[cfg(feature = "std")]
+pub mod he_channel;
 pub mod he_queue;
+#[cfg(feature = "std")]
 pub mod lq;
+pub mod metrics;
+#[cfg(feature = "std")]
 pub mod mutex_queue;
+mod node;
+mod notify;
+#[cfg(feature = "std")]
+pub mod priority_queue;
+#[cfg(feature = "std")]
+pub mod queue;
+#[cfg(feature = "std")]
+pub mod ring_queue;
+#[cfg(feature = "std")]
+pub mod sharded_queue;
+#[cfg(all(test, feature = "std"))]
+mod test_util;
+#[cfg(feature = "async")]
+mod waker_list;
+#[cfg(feature = "std")]
+pub mod weak_queue;
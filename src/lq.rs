@@ -1,10 +1,25 @@
 // a lockless empty linked list based queue
-// suffering from UAF and ABA problems
+//
+// `head` carries a tag bumped on every pop, so a stale CAS can't succeed on
+// a recycled address, and a node is published as a hazard before it is
+// dereferenced, so an unlinked node is only freed once nobody reads it
+//
+// the atomics are loom's under the `loom` feature, which has no `get_mut`,
+// so even exclusive access goes through relaxed loads and stores
 
-use std::{
-    ptr,
-    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
-};
+#[cfg(not(feature = "loom"))]
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::{cell::Cell, marker::PhantomData, ptr, sync::Arc};
+
+use crossbeam_utils::{Backoff, CachePadded};
+#[cfg(feature = "loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+#[cfg(feature = "dwcas")]
+use crate::dwcas::AtomicPair;
+#[cfg(feature = "metrics")]
+use crate::metrics::QueueStats;
+use crate::{hazard::Domain, metrics::Metrics};
 
 type NodePtr<T> = AtomicPtr<Node<T>>;
 
@@ -28,24 +43,198 @@ impl<T> Node<T> {
     }
 }
 
-/// WARNING:
-/// LinkedQueue does not fix ABA problem and UAF bug in multi-consumer scenarios
+// the tag lives in the top 16 bits, which user space addresses leave clear
+// on x86_64 and aarch64, except where the allocator tags heap pointers in
+// the top byte, as Android does; elsewhere it is left out, as hazards
+// alone already keep an address from coming back too early
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(target_os = "android")
+))]
+mod tag {
+    use super::Node;
+
+    const TAG_SHIFT: u32 = 48;
+    const ADDR_MASK: usize = (1 << TAG_SHIFT) - 1;
+
+    pub fn tagged<T>(p: *mut Node<T>, tag: usize) -> *mut Node<T> {
+        debug_assert_eq!(p.addr() & !ADDR_MASK, 0, "address overlaps the tag");
+        p.map_addr(|a| a | (tag << TAG_SHIFT))
+    }
+
+    pub fn untagged<T>(p: *mut Node<T>) -> *mut Node<T> {
+        p.map_addr(|a| a & ADDR_MASK)
+    }
+
+    pub fn tag<T>(p: *mut Node<T>) -> usize {
+        p.addr() >> TAG_SHIFT
+    }
+}
+
+#[cfg(not(all(
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(target_os = "android")
+)))]
+mod tag {
+    use super::Node;
+
+    pub fn tagged<T>(p: *mut Node<T>, _tag: usize) -> *mut Node<T> {
+        p
+    }
+
+    pub fn untagged<T>(p: *mut Node<T>) -> *mut Node<T> {
+        p
+    }
+
+    pub fn tag<T>(_p: *mut Node<T>) -> usize {
+        0
+    }
+}
+
+use tag::{tag, tagged, untagged};
+
+// a node on `head` and how many pops `head` had seen then
+type Seen<T> = (*mut Node<T>, usize);
+
+// `head` counts the pops, so a stale CAS fails even if the node it expects
+// was freed and its address handed out again
+enum Head<T> {
+    // the count in the top 16 bits, see `tagged`
+    Tagged(NodePtr<T>),
+    // the full count next to the pointer, swapped as one 16 byte word
+    #[cfg(feature = "dwcas")]
+    Wide(AtomicPair),
+}
+
+#[cfg(feature = "dwcas")]
+fn pack<T>((p, pops): Seen<T>) -> u128 {
+    p.expose_provenance() as u128 | (pops as u128) << 64
+}
+
+#[cfg(feature = "dwcas")]
+fn unpack<T>(v: u128) -> Seen<T> {
+    (
+        ptr::with_exposed_provenance_mut(v as u64 as usize),
+        (v >> 64) as usize,
+    )
+}
+
+impl<T> Head<T> {
+    fn new(node: *mut Node<T>) -> Self {
+        // loom can't see into the 16 byte CAS
+        #[cfg(feature = "dwcas")]
+        if !cfg!(feature = "loom") && AtomicPair::supported() {
+            return Head::Wide(AtomicPair::new(pack((node, 0))));
+        }
+        Head::Tagged(AtomicPtr::new(tagged(node, 0)))
+    }
+
+    fn load(&self) -> Seen<T> {
+        match self {
+            Head::Tagged(p) => {
+                let p = p.load(Ordering::SeqCst);
+                (untagged(p), tag(p))
+            }
+            #[cfg(feature = "dwcas")]
+            Head::Wide(p) => unpack(p.load()),
+        }
+    }
+
+    // moves on from `seen` to `next`, counting the pop
+    fn compare_exchange(&self, (p, pops): Seen<T>, next: *mut Node<T>) -> bool {
+        match self {
+            Head::Tagged(head) => head
+                .compare_exchange(
+                    tagged(p, pops),
+                    tagged(next, pops.wrapping_add(1) & 0xffff),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok(),
+            #[cfg(feature = "dwcas")]
+            Head::Wide(head) => head
+                .compare_exchange(pack((p, pops)), pack((next, pops.wrapping_add(1))))
+                .is_ok(),
+        }
+    }
+
+    fn get_mut(&mut self) -> *mut Node<T> {
+        match self {
+            Head::Tagged(p) => untagged(p.load(Ordering::Relaxed)),
+            #[cfg(feature = "dwcas")]
+            Head::Wide(p) => unpack::<T>(*p.get_mut()).0,
+        }
+    }
+
+    // `compare_exchange` for when nobody else can be racing on it
+    fn advance_mut(&mut self, next: *mut Node<T>) {
+        match self {
+            Head::Tagged(p) => {
+                let pops = tag(p.load(Ordering::Relaxed));
+                p.store(
+                    tagged(next, pops.wrapping_add(1) & 0xffff),
+                    Ordering::Relaxed,
+                );
+            }
+            #[cfg(feature = "dwcas")]
+            Head::Wide(p) => {
+                let pops = unpack::<T>(*p.get_mut()).1;
+                *p.get_mut() = pack((next, pops.wrapping_add(1)));
+            }
+        }
+    }
+}
+
+/// A lock-free queue over a hand rolled linked list.
+///
+/// Popped nodes are reclaimed with hazard pointers, in batches once
+/// enough of them are retired, or right away with
+/// [`reclaim_now`](Self::reclaim_now).
+///
+/// Items cross threads through it, so it is only `Send` and `Sync`
+/// for `T: Send`:
+///
+/// ```compile_fail
+/// fn shared<S: Send + Sync>(_: &S) {}
+/// shared(&l3queue::lq::LinkedQueue::<std::rc::Rc<i32>>::new());
+/// ```
+//
+// as in `HeQueue`, each hot field gets a cache line of its own, so
+// consumers on `head` and producers on `tail` don't false-share
 pub struct LinkedQueue<T> {
-    // empty list, which is much more easier to implement
-    len: AtomicUsize,
-    head: NodePtr<T>,
-    tail: NodePtr<T>,
+    // consumer side; an empty list, which is much more easier to implement
+    head: CachePadded<Head<T>>,
+    // producer side, never behind `head`: pop moves it on before
+    // unlinking the node it points to
+    tail: CachePadded<NodePtr<T>>,
+    // touched by both
+    len: CachePadded<AtomicUsize>,
+    // only checked by `try_push`
+    capacity: Option<usize>,
+    hazards: Domain,
+    metrics: Metrics,
+    // the nodes' items are ours, for dropck
+    _items: PhantomData<T>,
 }
 
+// the raw node pointers would make every `T` Send and Sync; items only
+// move in and out by value, and `&T` is only handed out through
+// `&mut self`, so sharing the queue just moves items between threads
+unsafe impl<T: Send> Send for LinkedQueue<T> {}
+unsafe impl<T: Send> Sync for LinkedQueue<T> {}
+
 impl<T> Default for LinkedQueue<T> {
     fn default() -> Self {
         let header = Box::new(Node::new_empty());
-        let head = AtomicPtr::from(Box::into_raw(header));
-        let tail = AtomicPtr::new(head.load(Ordering::SeqCst));
+        let header = Box::into_raw(header);
         Self {
-            len: AtomicUsize::new(0),
-            head,
-            tail,
+            head: CachePadded::new(Head::new(header)),
+            tail: CachePadded::new(AtomicPtr::new(header)),
+            len: CachePadded::new(AtomicUsize::new(0)),
+            capacity: None,
+            hazards: Domain::default(),
+            metrics: Metrics::default(),
+            _items: PhantomData,
         }
     }
 }
@@ -55,104 +244,496 @@ impl<T> LinkedQueue<T> {
         Self::default()
     }
 
+    /// A new queue in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    /// A queue whose `try_push` rejects items once `capacity` are queued.
+    ///
+    /// The bound is advisory: pushers racing past the length check may
+    /// each add one more, so up to `capacity` plus the number of
+    /// concurrent pushers can be queued, and `push` ignores it.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut q = Self::default();
+        q.capacity = Some(capacity);
+        q
+    }
+
+    /// The bound given to [`with_capacity`](Self::with_capacity), if any.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    // `link` counts a node before it is linked, so the counter may run
+    // ahead of the list: a non-zero count does not mean a pop would find
+    // a node, and no ordering on these loads changes that. Both take the
+    // same plain acquire load; the count is exact once pushes are joined.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.len
-            .compare_exchange(0, 0, Ordering::Release, Ordering::Relaxed)
-            .is_ok()
+        self.len() == 0
     }
 
     pub fn push(&self, item: T) {
-        let new_node = Box::new(Node::new(item));
-        let node_ptr: *mut Node<T> = Box::into_raw(new_node);
+        let node_ptr: *mut Node<T> = Box::into_raw(Box::new(Node::new(item)));
+        self.link(node_ptr, node_ptr, 1);
+    }
 
-        let old_tail = self.tail.load(Ordering::Acquire);
-        unsafe {
-            let mut tail_next = &(*old_tail).next;
-            while tail_next
-                .compare_exchange(
-                    ptr::null_mut(),
-                    node_ptr,
-                    Ordering::Release,
-                    Ordering::Relaxed,
-                )
-                .is_err()
-            {
-                let mut tail = tail_next.load(Ordering::Acquire);
-
-                // step to tail
-                loop {
-                    let nxt = (*tail).next.load(Ordering::Acquire);
-                    if nxt.is_null() {
-                        break;
-                    }
-                    tail = nxt;
-                }
+    /// Push `item` unless the queue is at capacity, in which case it is
+    /// handed back.
+    #[must_use = "a refused item is handed back in the error, and dropped if ignored"]
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        if self.capacity.is_some_and(|cap| self.len() >= cap) {
+            return Err(item);
+        }
+        self.push(item);
+        Ok(())
+    }
+
+    /// Push every item of `items`, in order, as one contiguous run.
+    ///
+    /// The nodes are chained up front and linked with a single CAS, so
+    /// no other push lands in between them.
+    pub fn push_batch<I: IntoIterator<Item = T>>(&self, items: I) {
+        let mut items = items.into_iter();
+        let Some(first) = items.next() else {
+            return;
+        };
+        let first: *mut Node<T> = Box::into_raw(Box::new(Node::new(first)));
+        let (mut last, mut n) = (first, 1);
+        for item in items {
+            let node = Box::into_raw(Box::new(Node::new(item)));
+            // still private, nobody else sees the chain yet
+            unsafe { (*last).next.store(node, Ordering::Relaxed) };
+            last = node;
+            n += 1;
+        }
+        self.link(first, last, n);
+    }
 
-                tail_next = &(*tail).next;
+    // append the private chain `first..=last` of `n` nodes
+    fn link(&self, first: *mut Node<T>, last: *mut Node<T>, n: usize) {
+        // counted before it is linked, so a pop can never uncount it first
+        self.len.fetch_add(n, Ordering::SeqCst);
+
+        let hazards = self.hazards.hazards();
+        let backoff = Backoff::new();
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            hazards.protect(0, tail);
+            // still the tail, so not unlinked yet and now safe to read
+            if self.tail.load(Ordering::SeqCst) != tail {
+                continue;
+            }
+            let next = unsafe { &(*tail).next };
+            match next.compare_exchange(
+                ptr::null_mut(),
+                first,
+                Ordering::Release,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    // if this loses, others already step through the chain
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        last,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                    break;
+                }
+                // move `tail` on by one for whoever lags behind, and back
+                // off before retrying so a crowd of pushers thins out
+                Err(nxt) => {
+                    self.metrics.cas_retry();
+                    let _ =
+                        self.tail
+                            .compare_exchange(tail, nxt, Ordering::Release, Ordering::Relaxed);
+                    backoff.spin();
+                }
             }
         }
-        let _ =
-            self.tail
-                .compare_exchange(old_tail, node_ptr, Ordering::Release, Ordering::Relaxed);
-        // finish insert, increase length;
-        self.len.fetch_add(1, Ordering::SeqCst);
+        drop(hazards);
+        self.metrics.pushed(n);
     }
 
     pub fn pop(&self) -> Option<T> {
-        let mut data = None;
         if self.is_empty() {
-            return data;
+            return None;
         }
-        unsafe {
-            let mut head;
+        let hazards = self.hazards.hazards();
+        let data = unsafe {
             loop {
-                head = self.head.load(Ordering::Acquire);
-                let next = (*head).next.load(Ordering::Acquire);
-
+                let head = self.head.load();
+                let h = head.0;
+                hazards.protect(0, h);
+                if self.head.load() != head {
+                    continue;
+                }
+                let next = (*h).next.load(Ordering::Acquire);
                 if next.is_null() {
-                    return None;
+                    break None;
+                }
+                // `h` still the head keeps `next` linked too
+                hazards.protect(1, next);
+                if self.head.load() != head {
+                    continue;
+                }
+                // a lagging tail must not be left on the node about to go
+                if self.tail.load(Ordering::Acquire) == h {
+                    let _ =
+                        self.tail
+                            .compare_exchange(h, next, Ordering::Release, Ordering::Relaxed);
+                    continue;
                 }
 
-                if self
-                    .head
-                    .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
-                    .is_ok()
-                {
-                    data = (*next).item.take();
-                    break;
+                if self.head.compare_exchange(head, next) {
+                    // only the winner touches the item, and the hazard on
+                    // `next` keeps it alive should it be popped meanwhile
+                    let data = (*next).item.take();
+                    drop(hazards);
+                    self.hazards.retire(h.cast(), free_node::<T>);
+                    break data;
                 }
+                self.metrics.cas_retry();
+            }
+        };
+        if data.is_some() {
+            let len = self.len.fetch_sub(1, Ordering::SeqCst);
+            debug_assert!(len != 0, "LinkedQueue length underflow on pop");
+            self.metrics.popped(1);
+        }
+
+        data
+    }
+
+    /// Free every popped node no other thread is still reading, returning
+    /// how many went.
+    ///
+    /// Pops do this by themselves every few dozen nodes, this is for
+    /// tests and for handing memory back at a quiet moment.
+    pub fn reclaim_now(&self) -> usize {
+        self.hazards.scan()
+    }
+
+    /// Walk the queued items from front to back without removing them.
+    ///
+    /// Exclusive access rules out any pop freeing a node mid-walk.
+    pub fn iter(&mut self) -> Iter<'_, T> {
+        Iter {
+            cur: self.head.get_mut(),
+            _queue: PhantomData,
+        }
+    }
+
+    /// The front item, left queued.
+    pub fn peek(&mut self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    /// The back item, left queued.
+    pub fn last(&mut self) -> Option<&T> {
+        // `tail` may lag, but never behind the front
+        let mut node = self.tail.load(Ordering::Relaxed);
+        unsafe {
+            loop {
+                let next = (*node).next.load(Ordering::Relaxed);
+                if next.is_null() {
+                    return (*node).item.as_ref();
+                }
+                node = next;
+            }
+        }
+    }
+
+    /// Pop items front to back, freeing each node on the way.
+    ///
+    /// Items not yet taken when the iterator is dropped stay queued.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { queue: self }
+    }
+
+    /// Drop every item, free every node but the sentinel, and leave the
+    /// queue empty and ready for use.
+    pub fn clear(&mut self) {
+        while self.pop_mut().is_some() {}
+        // with no operation running no hazard is held, so all of it goes
+        self.hazards.scan();
+    }
+
+    // `pop` with nobody else around: no hazards, and the old sentinel is
+    // freed right away rather than retired
+    fn pop_mut(&mut self) -> Option<T> {
+        let h = self.head.get_mut();
+        unsafe {
+            let next = (*h).next.load(Ordering::Relaxed);
+            if next.is_null() {
+                return None;
+            }
+            let data = (*next).item.take();
+            self.head.advance_mut(next);
+            if self.tail.load(Ordering::Relaxed) == h {
+                self.tail.store(next, Ordering::Relaxed);
+            }
+            drop(Box::from_raw(h));
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            self.metrics.popped(1);
+            data
+        }
+    }
+
+    /// Operation counters since the queue was created.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> QueueStats {
+        self.metrics.stats()
+    }
+}
+
+/// Iterator returned by [`LinkedQueue::drain`].
+pub struct Drain<'a, T> {
+    queue: &'a mut LinkedQueue<T>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop_mut()
+    }
+
+    // exact, nobody else can push or pop meanwhile
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.queue.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+/// Iterator returned by [`LinkedQueue::iter`].
+pub struct Iter<'a, T> {
+    // the node whose successor is visited next
+    cur: *mut Node<T>,
+    _queue: PhantomData<&'a LinkedQueue<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        unsafe {
+            let next = (*self.cur).next.load(Ordering::Relaxed);
+            if next.is_null() {
+                return None;
             }
-            // drop `head`
-            let _ = Box::from_raw(head);
+            self.cur = next;
+            (*next).item.as_ref()
+        }
+    }
+}
+
+/// The producer half returned by [`LinkedQueue::into_spsc`], clone it
+/// for more producers.
+pub struct LqProducer<T> {
+    queue: Arc<LinkedQueue<T>>,
+}
+
+/// The consumer half returned by [`LinkedQueue::into_spsc`].
+///
+/// There is only ever one, it can't be cloned or shared between threads:
+///
+/// ```compile_fail
+/// let (_tx, rx) = l3queue::lq::LinkedQueue::<i32>::new().into_spsc();
+/// let _rx2 = rx.clone();
+/// ```
+///
+/// ```compile_fail
+/// fn shared<S: Sync>(_: &S) {}
+/// let (_tx, rx) = l3queue::lq::LinkedQueue::<i32>::new().into_spsc();
+/// shared(&rx);
+/// ```
+///
+/// Nor can either half move items that can't cross threads:
+///
+/// ```compile_fail
+/// fn sent<S: Send>(_: S) {}
+/// let (tx, _rx) = l3queue::lq::LinkedQueue::<std::rc::Rc<i32>>::new().into_spsc();
+/// sent(tx);
+/// ```
+///
+/// ```compile_fail
+/// fn sent<S: Send>(_: S) {}
+/// let (_tx, rx) = l3queue::lq::LinkedQueue::<std::rc::Rc<i32>>::new().into_spsc();
+/// sent(rx);
+/// ```
+pub struct LqConsumer<T> {
+    queue: Arc<LinkedQueue<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> LinkedQueue<T> {
+    /// Split the queue into producers and its only consumer.
+    ///
+    /// With nobody else moving `head`, the consumer pops without a
+    /// hazard of its own. Items already queued stay queued.
+    pub fn into_spsc(self) -> (LqProducer<T>, LqConsumer<T>) {
+        let queue = Arc::new(self);
+        let tx = LqProducer {
+            queue: queue.clone(),
+        };
+        let rx = LqConsumer {
+            queue,
+            _not_sync: PhantomData,
         };
-        self.len.fetch_sub(1, Ordering::SeqCst);
+        (tx, rx)
+    }
+}
 
+impl<T> LqProducer<T> {
+    pub fn push(&self, item: T) {
+        self.queue.push(item);
+    }
+}
+
+impl<T> Clone for LqProducer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> LqConsumer<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let queue = &*self.queue;
+        let head = queue.head.load();
+        let h = head.0;
+        let next = unsafe { (*h).next.load(Ordering::Acquire) };
+        if next.is_null() {
+            return None;
+        }
+        // producers may still be on `h` through a lagging tail, which
+        // their hazards cover once it is moved on
+        if queue.tail.load(Ordering::Acquire) == h {
+            let _ = queue
+                .tail
+                .compare_exchange(h, next, Ordering::Release, Ordering::Relaxed);
+        }
+        let moved = queue.head.compare_exchange(head, next);
+        debug_assert!(moved, "LinkedQueue head moved under its only consumer");
+        let data = unsafe {
+            let data = (*next).item.take();
+            queue.hazards.retire(h.cast(), free_node::<T>);
+            data
+        };
+        let len = queue.len.fetch_sub(1, Ordering::SeqCst);
+        debug_assert!(len != 0, "LinkedQueue length underflow on pop");
+        queue.metrics.popped(1);
         data
     }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+impl<T> FromIterator<T> for LinkedQueue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let q = Self::new();
+        // nobody else can see the queue yet, so relaxed writes do
+        let mut tail = q.tail.load(Ordering::Relaxed);
+        let mut count = 0;
+        for item in iter {
+            let node = Box::into_raw(Box::new(Node::new(item)));
+            unsafe { (*tail).next.store(node, Ordering::Relaxed) };
+            tail = node;
+            count += 1;
+        }
+        q.tail.store(tail, Ordering::Relaxed);
+        q.len.store(count, Ordering::Relaxed);
+        q.metrics.pushed(count);
+        q
+    }
 }
 
+impl<T> Extend<T> for &LinkedQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.push_batch(iter);
+    }
+}
+
+impl<T> Extend<T> for LinkedQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        (&*self).extend(iter)
+    }
+}
+
+/// Iterator returned by [`LinkedQueue::into_iter`].
+pub struct IntoIter<T> {
+    // left valid after every step, so its drop frees whatever remains
+    queue: LinkedQueue<T>,
+}
+
+impl<T> IntoIterator for LinkedQueue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { queue: self }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.queue.pop_mut()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.queue.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
 impl<T> Drop for LinkedQueue<T> {
     fn drop(&mut self) {
-        while self.pop().is_some() {}
-        let h = self.head.load(Ordering::SeqCst);
+        while self.pop_mut().is_some() {}
+        let h = self.head.get_mut();
         unsafe {
-            // drop `h`
-            Box::from_raw(h);
+            // drop `h`, the retired nodes go with `hazards`
+            drop(Box::from_raw(h));
         }
     }
 }
 
+unsafe fn free_node<T>(node: *mut u8) {
+    drop(Box::from_raw(node.cast::<Node<T>>()));
+}
+
 #[cfg(test)]
 mod lq_test {
     use std::{
+        mem,
         sync::{
-            atomic::{AtomicI32, Ordering},
+            atomic::{AtomicI32, AtomicUsize, Ordering},
             Arc, Barrier,
         },
         thread,
     };
 
-    use crate::lq::LinkedQueue;
+    use crossbeam_utils::CachePadded;
+
+    use crate::{lq::LinkedQueue, test_util::check_logs};
 
     #[test]
     fn test_single() {
@@ -171,9 +752,53 @@ mod lq_test {
         assert_eq!(q.pop(), Some(4));
     }
 
+    #[test]
+    fn test_len() {
+        let q = LinkedQueue::new();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.len(), 2);
+        assert!(!q.is_empty());
+        q.pop();
+        q.pop();
+        q.pop();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_is_empty_across_threads() {
+        let q = Arc::new(LinkedQueue::new());
+        let p = q.clone();
+        // joining orders the push before the check
+        thread::spawn(move || p.push(1)).join().unwrap();
+        assert!(!q.is_empty());
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (ack, acked) = std::sync::mpsc::channel();
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            p.push(4);
+            tx.send(()).unwrap();
+            // hold the pops back until the check above is done
+            acked.recv().unwrap();
+            p.pop();
+            p.pop();
+            tx.send(()).unwrap();
+        });
+        rx.recv().unwrap();
+        assert!(!q.is_empty());
+        ack.send(()).unwrap();
+        rx.recv().unwrap();
+        assert!(q.is_empty());
+        producer.join().unwrap();
+    }
+
     #[test]
     fn test_concurrent_send() {
-        let pad = 100000_u128;
+        let pad = if cfg!(miri) { 64 } else { 100000_u128 };
 
         let p1 = Arc::new(LinkedQueue::new());
         let p2 = p1.clone();
@@ -206,7 +831,7 @@ mod lq_test {
 
     #[test]
     fn test_mpsc() {
-        let pad = 100_0000u128;
+        let pad = if cfg!(miri) { 64 } else { 100_0000u128 };
 
         let flag = Arc::new(AtomicI32::new(3));
         let flag1 = flag.clone();
@@ -248,4 +873,497 @@ mod lq_test {
         t3.join().unwrap();
         assert_eq!(sum, (0..(3 * pad)).sum());
     }
+
+    #[test]
+    fn test_try_push() {
+        let q = LinkedQueue::new();
+        assert_eq!(q.capacity(), None);
+        for i in 0..100 {
+            q.try_push(i).unwrap();
+        }
+
+        let cap = 4;
+        let q = Arc::new(LinkedQueue::with_capacity(cap));
+        assert_eq!(q.capacity(), Some(cap));
+        for i in 0..cap {
+            q.try_push(i.to_string()).unwrap();
+        }
+        assert_eq!(q.try_push(String::from("full")), Err(String::from("full")));
+
+        // nobody pops, so every producer gets its item back
+        let producers: Vec<_> = (0..2)
+            .map(|p| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let item = format!("producer {p}");
+                    assert_eq!(q.try_push(item.clone()), Err(item));
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        assert_eq!(q.len(), cap);
+
+        // room again once the consumer moves, and in order
+        let producers: Vec<_> = (0..2)
+            .map(|p| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut item = format!("producer {p}");
+                    while let Err(back) = q.try_push(item) {
+                        item = back;
+                        thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+        let mut got = vec![];
+        while got.len() < cap + 2 {
+            if let Some(item) = q.pop() {
+                got.push(item);
+            }
+        }
+        for p in producers {
+            p.join().unwrap();
+        }
+        assert_eq!(got[..cap], ["0", "1", "2", "3"]);
+        got[cap..].sort();
+        assert_eq!(got[cap..], ["producer 0", "producer 1"]);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_push_batch() {
+        let pad = if cfg!(miri) { 200 } else { 10_000 };
+
+        let q = Arc::new(LinkedQueue::new());
+        q.push_batch(Vec::new());
+        assert!(q.is_empty());
+
+        // one producer pushes one by one, the others in runs of `size`
+        let handles: Vec<_> = [1, 16, 100]
+            .into_iter()
+            .enumerate()
+            .map(|(p, size)| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for run in 0..pad / size {
+                        if size == 1 {
+                            q.push((p, run));
+                        } else {
+                            q.push_batch((run * size..(run + 1) * size).map(|i| (p, i)));
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut got = vec![];
+        while handles.iter().any(|h| !h.is_finished()) || !q.is_empty() {
+            if let Some(item) = q.pop() {
+                got.push(item);
+            }
+        }
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        for (p, size) in [(0, 1), (1, 16), (2, 100)] {
+            let mine: Vec<_> = got
+                .iter()
+                .filter(|(q, _)| *q == p)
+                .map(|&(_, i)| i)
+                .collect();
+            assert_eq!(mine, (0..pad / size * size).collect::<Vec<_>>());
+        }
+        // nobody else's item lands inside a run
+        for (at, &(p, i)) in got.iter().enumerate() {
+            let size = [1, 16, 100][p];
+            if i % size == 0 {
+                let run: Vec<_> = (0..size).map(|k| (p, i + k)).collect();
+                assert_eq!(got[at..at + size], run);
+            }
+        }
+    }
+
+    // `producers` tag what they push with their number, and as many
+    // consumers log what they pop until the producers are done
+    fn check_mpmc(q: Arc<LinkedQueue<(usize, usize)>>, producers: usize, pad: usize) {
+        let done = Arc::new(AtomicUsize::new(0));
+        let pushed: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push((n, i));
+                    }
+                    done.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        // consumers racing on `head` is what recycled nodes used to break
+        let popped: Vec<_> = (0..producers)
+            .map(|_| {
+                let q = q.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut log = vec![];
+                    while done.load(Ordering::SeqCst) != producers || !q.is_empty() {
+                        if let Some(item) = q.pop() {
+                            log.push(item);
+                        }
+                    }
+                    log
+                })
+            })
+            .collect();
+
+        for p in pushed {
+            p.join().unwrap();
+        }
+        let logs: Vec<_> = popped.into_iter().map(|c| c.join().unwrap()).collect();
+        check_logs(producers, pad, &logs);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_mpmc() {
+        let pad = if cfg!(miri) { 64 } else { 100_000 };
+        check_mpmc(Arc::new(LinkedQueue::new()), 4, pad);
+    }
+
+    #[cfg(feature = "dwcas")]
+    #[test]
+    fn test_mpmc_dwcas() {
+        let q = Arc::new(LinkedQueue::new());
+        // the 16 byte head wherever the CPU has the instruction
+        let wide = matches!(*q.head, super::Head::Wide(_));
+        assert_eq!(wide, crate::dwcas::AtomicPair::supported());
+        let pad = if cfg!(miri) { 64 } else { 50_000 };
+        check_mpmc(q, 8, pad);
+    }
+
+    #[test]
+    fn test_spsc_single() {
+        let q = LinkedQueue::new();
+        q.push(1);
+        let (tx, mut rx) = q.into_spsc();
+        for i in [1, 4, 5, 1, 4] {
+            tx.push(i);
+        }
+        assert_eq!(rx.len(), 6);
+        for i in [1, 1, 4, 5, 1, 4] {
+            assert_eq!(rx.pop(), Some(i));
+        }
+        assert_eq!(rx.pop(), None);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn test_spsc_mpsc() {
+        let producers = 3;
+        let pad = if cfg!(miri) { 64 } else { 100_000 };
+
+        let (tx, mut rx) = LinkedQueue::new().into_spsc();
+        let pushed: Vec<_> = (0..producers)
+            .map(|n| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        tx.push((n, i));
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        // the consumer may move to another thread, just not be shared
+        let consumer = thread::spawn(move || {
+            let mut log = vec![];
+            while log.len() != producers * pad {
+                if let Some(item) = rx.pop() {
+                    log.push(item);
+                }
+            }
+            assert_eq!(rx.pop(), None);
+            log
+        });
+
+        for p in pushed {
+            p.join().unwrap();
+        }
+        check_logs(producers, pad, &[consumer.join().unwrap()]);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut q = LinkedQueue::new();
+        assert_eq!(q.iter().next(), None);
+        assert_eq!(q.peek(), None);
+        assert_eq!(q.last(), None);
+
+        for s in ["1", "1", "4", "5"] {
+            q.push(String::from(s));
+        }
+        let items: Vec<_> = q.iter().map(String::as_str).collect();
+        assert_eq!(items, ["1", "1", "4", "5"]);
+        // nothing consumed
+        assert_eq!(q.peek().map(String::as_str), Some("1"));
+        assert_eq!(q.peek().map(String::as_str), Some("1"));
+        assert_eq!(q.last().map(String::as_str), Some("5"));
+        assert_eq!(q.len(), 4);
+
+        q.pop();
+        q.push(String::from("1"));
+        let items: Vec<_> = q.iter().map(String::as_str).collect();
+        assert_eq!(items, ["1", "4", "5", "1"]);
+        assert_eq!(q.last().map(String::as_str), Some("1"));
+
+        while q.pop().is_some() {}
+        assert_eq!(q.iter().count(), 0);
+        assert_eq!(q.peek(), None);
+        assert_eq!(q.last(), None);
+    }
+
+    #[test]
+    fn test_drain() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token(usize);
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut q = LinkedQueue::new();
+        for i in 0..8 {
+            q.push(Token(i));
+        }
+        {
+            let mut drain = q.drain();
+            assert_eq!(drain.len(), 8);
+            let got: Vec<_> = drain.by_ref().take(3).map(|t| t.0).collect();
+            assert_eq!(got, [0, 1, 2]);
+            assert_eq!(drain.len(), 5);
+        }
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+        // the rest stay queued, and no node was left to retire
+        assert_eq!(q.len(), 5);
+        assert_eq!(q.peek().map(|t| t.0), Some(3));
+        assert_eq!(q.reclaim_now(), 0);
+
+        let got: Vec<_> = q.drain().map(|t| t.0).collect();
+        assert_eq!(got, [3, 4, 5, 6, 7]);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 8);
+        assert!(q.is_empty());
+        assert_eq!(q.drain().next().map(|t| t.0), None);
+    }
+
+    #[test]
+    fn test_clear() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token(usize);
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut q = LinkedQueue::new();
+        for i in 0..6 {
+            q.push(Token(i));
+        }
+        // a couple popped the shared way, so their nodes are retired
+        q.pop();
+        q.pop();
+        q.clear();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 6);
+        assert_eq!(q.hazards.freed(), 2);
+        assert_eq!(q.len(), 0);
+        assert_eq!(q.tail.load(Ordering::Relaxed), q.head.get_mut());
+        assert!(q.pop().is_none());
+        q.clear();
+
+        // and reusable from several threads afterwards
+        let threads = 4;
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+        let q = Arc::new(q);
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push(Token(t * pad + i));
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let mut q = Arc::into_inner(q).unwrap();
+        assert_eq!(q.len(), threads * pad);
+        let mut got: Vec<_> = q.drain().map(|t| t.0).collect();
+        got.sort();
+        assert_eq!(got, (0..threads * pad).collect::<Vec<_>>());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 6 + threads * pad);
+    }
+
+    #[test]
+    fn test_collect() {
+        let q: LinkedQueue<_> = (0..6).collect();
+        assert_eq!(q.len(), 6);
+        assert_eq!(q.pop(), Some(0));
+        q.push(6);
+        assert_eq!(q.into_iter().collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+
+        let q: LinkedQueue<i32> = [].into_iter().collect();
+        assert!(q.is_empty());
+        assert_eq!(q.into_iter().next(), None);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut q = LinkedQueue::new();
+        q.push(0);
+        (&q).extend(1..4);
+        q.extend([4, 5]);
+        assert_eq!(q.len(), 6);
+        assert_eq!(q.drain().collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_into_iter_early_drop() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token(usize);
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let q: LinkedQueue<_> = (0..8).map(Token).collect();
+        q.pop();
+        let mut iter = q.into_iter();
+        assert_eq!(iter.len(), 7);
+        assert_eq!(iter.next().map(|t| t.0), Some(1));
+        assert_eq!(iter.next().map(|t| t.0), Some(2));
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+        // the other five go with the iterator
+        drop(iter);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn test_hot_fields_apart() {
+        let line = mem::align_of::<CachePadded<u8>>();
+        let mut offsets = [
+            mem::offset_of!(LinkedQueue<u64>, head),
+            mem::offset_of!(LinkedQueue<u64>, tail),
+            mem::offset_of!(LinkedQueue<u64>, len),
+        ];
+        offsets.sort();
+        assert!(offsets.windows(2).all(|w| w[1] - w[0] >= line));
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let q = LinkedQueue::new();
+        for i in 0..3 {
+            q.push(i);
+        }
+        for _ in 0..3 {
+            q.pop();
+        }
+        // below the scan threshold, so nothing went yet
+        assert_eq!(q.reclaim_now(), 3);
+        assert_eq!(q.reclaim_now(), 0);
+
+        // every item dropped and every popped node freed exactly once
+        struct Token(Arc<AtomicUsize>);
+        impl Drop for Token {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let threads = 4;
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+        let drops = Arc::new(AtomicUsize::new(0));
+        let q = Arc::new(LinkedQueue::new());
+        let popped = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let (q, drops, popped) = (q.clone(), drops.clone(), popped.clone());
+                thread::spawn(move || {
+                    for _ in 0..pad {
+                        q.push(Token(drops.clone()));
+                    }
+                    while popped.load(Ordering::SeqCst) != threads * pad {
+                        if q.pop().is_some() {
+                            popped.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        q.reclaim_now();
+        assert_eq!(q.hazards.freed(), threads * pad);
+        assert_eq!(drops.load(Ordering::SeqCst), threads * pad);
+    }
+
+    #[test]
+    fn test_zst() {
+        let pad = if cfg!(miri) { 64 } else { 100_000 };
+
+        let q = LinkedQueue::new();
+        for _ in 0..pad {
+            q.push(());
+        }
+        assert!(!q.is_empty());
+        for _ in 0..(pad / 2) {
+            assert_eq!(q.pop(), Some(()));
+        }
+        assert!(!q.is_empty());
+        while q.pop().is_some() {}
+        assert!(q.is_empty());
+        assert_eq!(q.pop(), None);
+
+        // a zero-sized payload with drop glue, left in the queue on drop
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let q = LinkedQueue::new();
+        for _ in 0..pad {
+            q.push(Token);
+        }
+        for _ in 0..(pad / 2) {
+            drop(q.pop());
+        }
+        drop(q);
+        assert_eq!(DROPS.load(Ordering::SeqCst), pad);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_stats() {
+        let q = LinkedQueue::new();
+        q.push(1);
+        q.push(4);
+        q.pop();
+        let stats = q.stats();
+        assert_eq!((stats.pushes, stats.pops, stats.cas_retries), (2, 1, 0));
+        // popping an empty queue is not counted
+        q.pop();
+        q.pop();
+        assert_eq!(q.stats().pops, 2);
+    }
 }
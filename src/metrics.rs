@@ -0,0 +1,176 @@
+// per-queue operation counters, compiled down to nothing
+// unless the `metrics` (or for `HeQueue`'s contention counters, `stats`)
+// feature is enabled
+
+#[cfg(feature = "stats")]
+use alloc::sync::Arc;
+#[cfg(any(feature = "metrics", feature = "stats"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A snapshot of a lock-free queue's counters, see `stats` on each queue.
+///
+/// Counters are updated with relaxed ordering, so a snapshot taken while
+/// the queue is in use may not be consistent across fields.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    pub pushes: usize,
+    pub pops: usize,
+    /// CAS attempts that lost a race and had to be retried.
+    pub cas_retries: usize,
+    /// `HeQueue`'s contention counters, left zeroed by the other queues.
+    #[cfg(feature = "stats")]
+    pub contention: HeStats,
+}
+
+// zero sized with the feature off, every method is then a no-op
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    #[cfg(feature = "metrics")]
+    pushes: AtomicUsize,
+    #[cfg(feature = "metrics")]
+    pops: AtomicUsize,
+    #[cfg(feature = "metrics")]
+    cas_retries: AtomicUsize,
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+impl Metrics {
+    #[inline]
+    pub fn pushed(&self, n: usize) {
+        #[cfg(feature = "metrics")]
+        self.pushes.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn popped(&self, n: usize) {
+        #[cfg(feature = "metrics")]
+        self.pops.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn cas_retry(&self) {
+        #[cfg(feature = "metrics")]
+        self.cas_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            pushes: self.pushes.load(Ordering::Relaxed),
+            pops: self.pops.load(Ordering::Relaxed),
+            cas_retries: self.cas_retries.load(Ordering::Relaxed),
+            #[cfg(feature = "stats")]
+            contention: HeStats::default(),
+        }
+    }
+}
+
+/// A snapshot of `HeQueue`'s contention counters, see `HeQueue::stats`.
+///
+/// Like [`QueueStats`] these are relaxed and may be torn across fields.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeStats {
+    /// CAS attempts to link a new node (or chain) after the tail.
+    pub push_attempts: usize,
+    /// Link attempts that found the tail already taken.
+    pub push_cas_failures: usize,
+    /// Times a thread moved a lagging `tail` forward for another one.
+    pub tail_helps: usize,
+    /// Times a pop lost the race on `head` and went around again.
+    pub pop_retries: usize,
+}
+
+#[cfg(feature = "stats")]
+impl HeStats {
+    /// Fraction of link attempts that failed, `0.0` when nothing was pushed.
+    pub fn push_failure_rate(&self) -> f64 {
+        if self.push_attempts == 0 {
+            return 0.0;
+        }
+        self.push_cas_failures as f64 / self.push_attempts as f64
+    }
+}
+
+// `HeQueue`'s contention counters, zero sized with the `stats` feature off
+#[derive(Debug, Default)]
+pub(crate) struct HeCounters {
+    #[cfg(feature = "stats")]
+    push_attempts: AtomicUsize,
+    #[cfg(feature = "stats")]
+    push_cas_failures: AtomicUsize,
+    #[cfg(feature = "stats")]
+    tail_helps: AtomicUsize,
+    #[cfg(feature = "stats")]
+    pop_retries: AtomicUsize,
+    // nodes handed to the epoch collector and not freed yet, shared with
+    // the deferred destructors as they may outlive the queue
+    #[cfg(feature = "stats")]
+    retired: Arc<AtomicUsize>,
+}
+
+impl HeCounters {
+    #[inline]
+    pub fn push_attempt(&self) {
+        #[cfg(feature = "stats")]
+        self.push_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn push_cas_failure(&self) {
+        #[cfg(feature = "stats")]
+        self.push_cas_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn tail_help(&self) {
+        #[cfg(feature = "stats")]
+        self.tail_helps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn pop_retry(&self) {
+        #[cfg(feature = "stats")]
+        self.pop_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> HeStats {
+        HeStats {
+            push_attempts: self.push_attempts.load(Ordering::Relaxed),
+            push_cas_failures: self.push_cas_failures.load(Ordering::Relaxed),
+            tail_helps: self.tail_helps.load(Ordering::Relaxed),
+            pop_retries: self.pop_retries.load(Ordering::Relaxed),
+        }
+    }
+
+    // count a node about to be retired, the destructor must call
+    // `reclaimed` on the returned counter once it frees it
+    #[cfg(feature = "stats")]
+    pub fn retiring(&self) -> Arc<AtomicUsize> {
+        self.retired.fetch_add(1, Ordering::Relaxed);
+        self.retired.clone()
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn retired(&self) -> usize {
+        self.retired.load(Ordering::Relaxed)
+    }
+
+    // a gauge rather than a counter, so `reset` leaves `retired` alone
+    #[cfg(feature = "stats")]
+    pub fn reset(&self) {
+        self.push_attempts.store(0, Ordering::Relaxed);
+        self.push_cas_failures.store(0, Ordering::Relaxed);
+        self.tail_helps.store(0, Ordering::Relaxed);
+        self.pop_retries.store(0, Ordering::Relaxed);
+    }
+}
+
+// called by a deferred destructor once its node is freed
+#[cfg(feature = "stats")]
+pub(crate) fn reclaimed(retired: &AtomicUsize) {
+    let n = retired.fetch_sub(1, Ordering::Relaxed);
+    debug_assert!(n != 0, "retired node count underflow");
+}
@@ -1,12 +1,83 @@
-use std::{collections::LinkedList, sync::Mutex};
+use std::{
+    collections::VecDeque,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, TryLockError,
+    },
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use crossbeam_utils::Backoff;
+
+#[derive(Debug)]
 pub struct MutexQueue<T> {
-    inner: Mutex<LinkedList<T>>,
+    pub(crate) inner: Mutex<VecDeque<T>>,
+    // present in fair mode, hands the lock out in arrival order
+    ticket: Option<TicketLock>,
+    // registered by `poll_pop` on an empty queue, woken by the next push
+    wakers: Mutex<Vec<Waker>>,
+    // notified by any pop that leaves the queue empty
+    emptied: Condvar,
+    // notified by every push, for `recv_all` to sleep on
+    pushed: Condvar,
+}
+
+/// Returned by [`MutexQueue::try_pop`] and [`MutexQueue::try_push`] when
+/// the lock is held elsewhere, the latter handing its item back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock<T = ()>(pub T);
+
+// `std::sync::Mutex` makes no promise about who gets the lock next,
+// so a thread that keeps re-locking can starve the others
+#[derive(Debug, Default)]
+struct TicketLock {
+    next: AtomicUsize,
+    serving: AtomicUsize,
+}
+
+// the queue contents, plus the ticket to hand on once they are released
+struct QueueGuard<'a, T> {
+    guard: MutexGuard<'a, VecDeque<T>>,
+    ticket: Option<&'a TicketLock>,
+}
+
+impl<T> Deref for QueueGuard<'_, T> {
+    type Target = VecDeque<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for QueueGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for QueueGuard<'_, T> {
+    fn drop(&mut self) {
+        // the mutex itself is only released after this, so the next ticket
+        // holder may block on it for a moment, but never behind anyone else
+        if let Some(ticket) = self.ticket {
+            ticket.serving.fetch_add(1, Ordering::Release);
+        }
+    }
 }
 
 impl<T> Default for MutexQueue<T> {
     fn default() -> Self {
-        let inner = Mutex::new(LinkedList::new());
-        Self { inner }
+        let inner = Mutex::new(VecDeque::new());
+        Self {
+            inner,
+            ticket: None,
+            wakers: Mutex::default(),
+            emptied: Condvar::new(),
+            pushed: Condvar::new(),
+        }
     }
 }
 
@@ -15,33 +86,392 @@ impl<T> MutexQueue<T> {
         Self::default()
     }
 
+    /// A new queue in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    /// A queue whose lock is granted strictly first come, first served,
+    /// so no producer or consumer can be starved under contention.
+    ///
+    /// This costs throughput: waiters spin until their turn instead of
+    /// sleeping, and the lock can't go to whichever thread happens to be
+    /// running, so a preempted thread holding the next ticket stalls
+    /// everyone behind it. Prefer [`MutexQueue::new`] unless starvation
+    /// is actually observed.
+    pub fn fair() -> Self {
+        Self {
+            ticket: Some(TicketLock::default()),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_fair(&self) -> bool {
+        self.ticket.is_some()
+    }
+
+    fn lock(&self) -> QueueGuard<'_, T> {
+        if let Some(ticket) = &self.ticket {
+            let mine = ticket.next.fetch_add(1, Ordering::Relaxed);
+            let backoff = Backoff::new();
+            while ticket.serving.load(Ordering::Acquire) != mine {
+                backoff.snooze();
+            }
+        }
+        let guard = self.inner.lock().unwrap_or_else(|e| {
+            // no `QueueGuard` will pass our ticket on, and without that
+            // every fair locker after us would spin forever
+            self.pass_on();
+            panic!("{e}")
+        });
+        QueueGuard {
+            guard,
+            ticket: self.ticket.as_ref(),
+        }
+    }
+
+    // let the next ticket holder in, for when we give up our turn
+    // without a `QueueGuard` to do it
+    fn pass_on(&self) {
+        if let Some(ticket) = &self.ticket {
+            ticket.serving.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    // `lock` unless that would wait; in fair mode a ticket is only drawn
+    // when it is the one being served
+    fn try_lock(&self) -> Option<QueueGuard<'_, T>> {
+        if let Some(ticket) = &self.ticket {
+            let serving = ticket.serving.load(Ordering::Acquire);
+            ticket
+                .next
+                .compare_exchange(serving, serving + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .ok()?;
+        }
+        match self.inner.try_lock() {
+            Ok(guard) => Some(QueueGuard {
+                guard,
+                ticket: self.ticket.as_ref(),
+            }),
+            // either way we still hold the line, so pass it on
+            Err(TryLockError::WouldBlock) => {
+                self.pass_on();
+                None
+            }
+            Err(TryLockError::Poisoned(e)) => {
+                self.pass_on();
+                panic!("{e}")
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let guard = self.lock();
+        guard.len()
+    }
+
     pub fn is_empty(&self) -> bool {
-        let guard = self.inner.lock().unwrap();
-        guard.is_empty()
+        self.len() == 0
     }
 
     pub fn push(&self, item: T) {
-        let mut guard = self.inner.lock().unwrap();
+        let mut guard = self.lock();
         guard.push_back(item);
+        drop(guard);
+        self.pushed.notify_one();
+        self.wake_all();
     }
 
     pub fn pop(&self) -> Option<T> {
-        let mut guard = self.inner.lock().unwrap();
-        guard.pop_front()
+        let mut guard = self.lock();
+        let item = guard.pop_front();
+        if item.is_some() {
+            self.popped(&guard);
+        }
+        item
+    }
+
+    /// Pop an item, or return [`WouldBlock`] rather than wait for the lock.
+    pub fn try_pop(&self) -> Result<Option<T>, WouldBlock> {
+        let mut guard = self.try_lock().ok_or(WouldBlock(()))?;
+        let item = guard.pop_front();
+        if item.is_some() {
+            self.popped(&guard);
+        }
+        Ok(item)
+    }
+
+    /// Push `item`, or hand it back rather than wait for the lock.
+    #[must_use = "a refused item is handed back in the error, and dropped if ignored"]
+    pub fn try_push(&self, item: T) -> Result<(), WouldBlock<T>> {
+        let Some(mut guard) = self.try_lock() else {
+            return Err(WouldBlock(item));
+        };
+        guard.push_back(item);
+        drop(guard);
+        self.pushed.notify_one();
+        self.wake_all();
+        Ok(())
+    }
+
+    // called with the lock still held after taking items out
+    fn popped(&self, guard: &VecDeque<T>) {
+        if guard.is_empty() {
+            self.emptied.notify_all();
+        }
+    }
+
+    /// Block until the queue is empty, e.g. for a producer to wait
+    /// until consumers have taken everything before it exits.
+    ///
+    /// In fair mode this waits on the lock without a ticket, as it
+    /// only reads the queue and must not hold the line while asleep.
+    pub fn wait_until_empty(&self) {
+        let guard = self.inner.lock().unwrap();
+        let _guard = self.emptied.wait_while(guard, |q| !q.is_empty()).unwrap();
+    }
+
+    /// Like [`MutexQueue::wait_until_empty`], but gives up after `timeout`,
+    /// returning whether the queue was empty.
+    pub fn wait_until_empty_timeout(&self, timeout: Duration) -> bool {
+        let guard = self.inner.lock().unwrap();
+        let (guard, _) = self
+            .emptied
+            .wait_timeout_while(guard, timeout, |q| !q.is_empty())
+            .unwrap();
+        guard.is_empty()
+    }
+
+    /// Block until at least one item is queued, then take everything
+    /// queued at that point, in order.
+    ///
+    /// Like [`MutexQueue::wait_until_empty`], in fair mode this waits
+    /// on the lock without a ticket, so it doesn't hold the line asleep.
+    pub fn recv_all(&self) -> Vec<T> {
+        let guard = self.inner.lock().unwrap();
+        let mut guard = self.pushed.wait_while(guard, |q| q.is_empty()).unwrap();
+        let items: Vec<T> = guard.drain(..).collect();
+        self.popped(&guard);
+        items
+    }
+
+    /// Block until at least one item is queued, then keep taking items
+    /// as long as each arrives within `idle` of the previous one, for
+    /// at most `max` items.
+    ///
+    /// Waits without a ticket in fair mode, like [`MutexQueue::recv_all`].
+    pub fn drain_timeout(&self, idle: Duration, max: usize) -> Vec<T> {
+        let mut batch = Vec::new();
+        if max == 0 {
+            return batch;
+        }
+        let guard = self.inner.lock().unwrap();
+        let mut guard = self.pushed.wait_while(guard, |q| q.is_empty()).unwrap();
+        loop {
+            while batch.len() < max {
+                match guard.pop_front() {
+                    Some(item) => batch.push(item),
+                    None => break,
+                }
+            }
+            if batch.len() == max {
+                break;
+            }
+            let (next, wait) = self
+                .pushed
+                .wait_timeout_while(guard, idle, |q| q.is_empty())
+                .unwrap();
+            guard = next;
+            if wait.timed_out() {
+                break;
+            }
+        }
+        self.popped(&guard);
+        batch
+    }
+
+    /// Pop the front item, or register `waker` to be woken by the next push.
+    ///
+    /// For driving the queue from a hand-written future or executor.
+    pub fn poll_pop(&self, waker: &Waker) -> Poll<T> {
+        let mut guard = self.lock();
+        if let Some(item) = guard.pop_front() {
+            self.popped(&guard);
+            return Poll::Ready(item);
+        }
+        // registered under the queue lock, so a push can't slip in
+        // between the check above and the registration
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+
+    // called after the pushed item is released to consumers
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        for w in wakers {
+            w.wake();
+        }
+    }
+
+    // pop from the front as long as `pred` holds, under a single lock
+    // the first item failing `pred` stays at the front
+    pub fn pop_while<F: FnMut(&T) -> bool>(&self, mut pred: F) -> Vec<T> {
+        let mut guard = self.lock();
+        let mut popped = Vec::new();
+        while let Some(front) = guard.front() {
+            if !pred(front) {
+                break;
+            }
+            popped.extend(guard.pop_front());
+        }
+        if !popped.is_empty() {
+            self.popped(&guard);
+        }
+        popped
+    }
+
+    /// Move up to `n` items from the front of this queue to the back of
+    /// `dst` in one step, returning how many were moved.
+    ///
+    /// Both locks are held for the move, always taken in address order,
+    /// so transfers running the other way can't deadlock with this one.
+    /// Moving to the queue itself moves nothing.
+    pub fn transfer(&self, dst: &MutexQueue<T>, n: usize) -> usize {
+        if ptr::eq(self, dst) {
+            return 0;
+        }
+        let (mut src_guard, mut dst_guard) = if (self as *const Self) < (dst as *const Self) {
+            let src_guard = self.lock();
+            (src_guard, dst.lock())
+        } else {
+            let dst_guard = dst.lock();
+            (self.lock(), dst_guard)
+        };
+        let moved = n.min(src_guard.len());
+        if moved == 0 {
+            return 0;
+        }
+        dst_guard.extend(src_guard.drain(..moved));
+        self.popped(&src_guard);
+        drop(src_guard);
+        drop(dst_guard);
+        dst.pushed.notify_all();
+        dst.wake_all();
+        moved
+    }
+
+    /// Visit every item front to back under a single lock, keeping those
+    /// `f` returns `true` for, as changed by `f`, and dropping the rest.
+    pub fn drain_in_place<F: FnMut(&mut T) -> bool>(&self, f: F) {
+        let mut guard = self.lock();
+        let before = guard.len();
+        guard.retain_mut(f);
+        if guard.len() < before {
+            self.popped(&guard);
+        }
+    }
+
+    pub fn from_vec(v: Vec<T>) -> Self {
+        let inner = Mutex::new(VecDeque::from(v));
+        Self {
+            inner,
+            ticket: None,
+            wakers: Mutex::default(),
+            emptied: Condvar::new(),
+            pushed: Condvar::new(),
+        }
+    }
+
+    // consumes the queue, so the lock is taken apart instead of being acquired
+    pub fn into_vec(self) -> Vec<T> {
+        Vec::from(self.into_inner())
+    }
+
+    /// The backing deque, front first, with no copying.
+    pub fn into_inner(self) -> VecDeque<T> {
+        self.inner.into_inner().unwrap()
+    }
+
+    pub fn capacity(&self) -> usize {
+        let guard = self.lock();
+        guard.capacity()
+    }
+
+    /// Release the memory left over from a burst of pushes.
+    ///
+    /// This is O(n) and holds the lock while it reallocates,
+    /// briefly blocking producers and consumers.
+    pub fn shrink_to_fit(&self) {
+        let mut guard = self.lock();
+        guard.shrink_to_fit();
+    }
+}
+
+impl<T> From<Vec<T>> for MutexQueue<T> {
+    fn from(v: Vec<T>) -> Self {
+        Self::from_vec(v)
+    }
+}
+
+impl<T> From<MutexQueue<T>> for Vec<T> {
+    fn from(q: MutexQueue<T>) -> Self {
+        q.into_vec()
+    }
+}
+
+// snapshots the contents under the lock, the clone shares nothing with the source
+// but keeps its fairness mode
+impl<T: Clone> Clone for MutexQueue<T> {
+    fn clone(&self) -> Self {
+        let guard = self.lock();
+        let inner = Mutex::new(guard.clone());
+        Self {
+            inner,
+            ticket: self.ticket.as_ref().map(|_| TicketLock::default()),
+            wakers: Mutex::default(),
+            emptied: Condvar::new(),
+            pushed: Condvar::new(),
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for MutexQueue<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        // always lock the queue at the lower address first, so that
+        // `a == b` and `b == a` racing each other cannot deadlock
+        let (first, second) = if (self as *const Self) < (other as *const Self) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let first = first.lock();
+        let second = second.lock();
+        *first == *second
     }
 }
 
 #[cfg(test)]
 mod test {
     use std::{
+        collections::VecDeque,
         sync::{
-            atomic::{AtomicI32, Ordering},
+            atomic::{AtomicI32, AtomicUsize, Ordering},
             Arc, Barrier,
         },
+        task::{Poll, Wake, Waker},
         thread,
+        time::Duration,
     };
 
-    use super::MutexQueue;
+    use crossbeam_utils::Backoff;
+
+    use super::{MutexQueue, WouldBlock};
     #[test]
     fn test_single() {
         let q = MutexQueue::new();
@@ -58,6 +488,331 @@ mod test {
         assert_eq!(q.pop(), Some(1));
         assert_eq!(q.pop(), Some(4));
     }
+
+    #[test]
+    fn test_len() {
+        let q = MutexQueue::new();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.len(), 2);
+        assert!(!q.is_empty());
+        q.pop();
+        q.pop();
+        q.pop();
+        assert_eq!(q.len(), 0);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_try_pop() {
+        for q in [MutexQueue::new(), MutexQueue::fair()] {
+            assert_eq!(q.try_pop(), Ok(None));
+            q.try_push(1).unwrap();
+            q.push(4);
+            assert_eq!(q.try_pop(), Ok(Some(1)));
+
+            let q = Arc::new(q);
+            let held = q.lock();
+            let c = q.clone();
+            let refused = thread::spawn(move || (c.try_pop(), c.try_push(5)))
+                .join()
+                .unwrap();
+            assert_eq!(refused, (Err(WouldBlock(())), Err(WouldBlock(5))));
+            drop(held);
+
+            // a refusal leaves the lock usable, fair or not
+            assert_eq!(q.try_pop(), Ok(Some(4)));
+            q.push(5);
+            assert_eq!(q.pop(), Some(5));
+            assert_eq!(q.try_pop(), Ok(None));
+        }
+    }
+
+    #[test]
+    fn test_wait_until_empty() {
+        for q in [MutexQueue::new(), MutexQueue::fair()] {
+            let q = Arc::new(q);
+            // nothing to wait for
+            q.wait_until_empty();
+
+            for i in 0..5 {
+                q.push(i);
+            }
+            let popped = Arc::new(AtomicUsize::new(0));
+            let c = q.clone();
+            let p = popped.clone();
+            let consumer = thread::spawn(move || {
+                while p.load(Ordering::SeqCst) < 5 {
+                    // slow, so the producer is already waiting
+                    thread::sleep(Duration::from_millis(10));
+                    if c.pop().is_some() {
+                        p.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            });
+            q.wait_until_empty();
+            // the last pop emptied the queue, its count may lag behind
+            assert!(q.is_empty());
+            consumer.join().unwrap();
+            assert_eq!(popped.load(Ordering::SeqCst), 5);
+        }
+    }
+
+    #[test]
+    fn test_wait_until_empty_timeout() {
+        let q = Arc::new(MutexQueue::new());
+        assert!(q.wait_until_empty_timeout(Duration::from_millis(10)));
+        q.push(1);
+        q.push(4);
+        // nobody pops
+        assert!(!q.wait_until_empty_timeout(Duration::from_millis(10)));
+
+        let c = q.clone();
+        let consumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            c.pop_while(|_| true)
+        });
+        assert!(q.wait_until_empty_timeout(Duration::from_secs(10)));
+        assert_eq!(consumer.join().unwrap(), vec![1, 4]);
+    }
+
+    #[test]
+    fn test_recv_all() {
+        for q in [MutexQueue::new(), MutexQueue::fair()] {
+            let q = Arc::new(q);
+
+            // a burst queued before the consumer gets to it comes in one go
+            let barrier = Arc::new(Barrier::new(2));
+            let (c, b) = (q.clone(), barrier.clone());
+            let consumer = thread::spawn(move || {
+                b.wait();
+                c.recv_all()
+            });
+            for i in 0..5 {
+                q.push(i);
+            }
+            barrier.wait();
+            assert_eq!(consumer.join().unwrap(), vec![0, 1, 2, 3, 4]);
+            assert!(q.is_empty());
+
+            // an empty queue blocks until the next push
+            let c = q.clone();
+            let consumer = thread::spawn(move || c.recv_all());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!consumer.is_finished());
+            q.push(5);
+            assert_eq!(consumer.join().unwrap(), vec![5]);
+        }
+    }
+
+    #[test]
+    fn test_drain_timeout() {
+        let q = Arc::new(MutexQueue::new());
+        let p = q.clone();
+        let producer = thread::spawn(move || {
+            for i in 0..5 {
+                p.push(i);
+            }
+            // a gap much longer than `idle` ends the first batch
+            thread::sleep(Duration::from_millis(300));
+            for i in 5..10 {
+                p.push(i);
+            }
+        });
+
+        let idle = Duration::from_millis(100);
+        assert_eq!(q.drain_timeout(idle, 100), vec![0, 1, 2, 3, 4]);
+        assert_eq!(q.drain_timeout(idle, 100), vec![5, 6, 7, 8, 9]);
+        producer.join().unwrap();
+
+        // `max` cuts a batch short, leaving the rest queued
+        for i in 0..10 {
+            q.push(i);
+        }
+        assert_eq!(q.drain_timeout(idle, 4), vec![0, 1, 2, 3]);
+        assert_eq!(q.len(), 6);
+        assert_eq!(q.drain_timeout(idle, 0), vec![]);
+        assert_eq!(q.drain_timeout(idle, 100), vec![4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_vec_round_trip() {
+        let v = vec![1, 1, 4, 5, 1, 4];
+        assert_eq!(MutexQueue::from_vec(v.clone()).into_vec(), v);
+
+        let q = MutexQueue::from_vec(v);
+        assert_eq!(q.pop(), Some(1));
+        q.push(9);
+        assert_eq!(q.into_vec(), vec![1, 4, 5, 1, 4, 9]);
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let q = MutexQueue::new();
+        for i in [1, 1, 4, 5] {
+            q.push(i);
+        }
+        assert_eq!(q.pop(), Some(1));
+        q.push(1);
+        let deque = q.into_inner();
+        assert_eq!(deque, VecDeque::from([1, 4, 5, 1]));
+        assert_eq!(deque.front(), Some(&1));
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let q: MutexQueue<_> = vec![1, 1, 4].into();
+        q.push(5);
+        assert_eq!(q.pop(), Some(1));
+        let v: Vec<_> = q.into();
+        assert_eq!(v, vec![1, 4, 5]);
+
+        let q = MutexQueue::from(Vec::<i32>::new());
+        assert!(q.is_empty());
+        assert_eq!(Vec::from(q), vec![]);
+    }
+
+    #[test]
+    fn test_pop_while() {
+        let q = MutexQueue::from_vec(vec![1, 2, 3, 10, 4]);
+        assert_eq!(q.pop_while(|x| *x < 5), vec![1, 2, 3]);
+        assert_eq!(q.pop_while(|x| *x < 5), vec![]);
+        assert_eq!(q.into_vec(), vec![10, 4]);
+
+        let q = MutexQueue::from_vec(vec![1, 2]);
+        assert_eq!(q.pop_while(|_| true), vec![1, 2]);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_drain_in_place() {
+        let q = MutexQueue::from_vec(vec![1, 2, 3, 4, 5, 6]);
+        // double the even ones, drop the odd ones
+        q.drain_in_place(|x| {
+            if *x % 2 == 1 {
+                return false;
+            }
+            *x *= 2;
+            true
+        });
+        assert_eq!(q.into_vec(), vec![4, 8, 12]);
+
+        // emptying it wakes those waiting for that
+        let q = Arc::new(MutexQueue::from_vec(vec![1, 3]));
+        let w = q.clone();
+        let waiter = thread::spawn(move || w.wait_until_empty());
+        thread::sleep(Duration::from_millis(50));
+        let mut seen = vec![];
+        q.drain_in_place(|x| {
+            seen.push(*x);
+            false
+        });
+        assert_eq!(seen, vec![1, 3]);
+        waiter.join().unwrap();
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn test_transfer() {
+        let a = MutexQueue::from_vec(vec![1, 2, 3, 4, 5]);
+        let b = MutexQueue::from_vec(vec![0]);
+        assert_eq!(a.transfer(&b, 3), 3);
+        assert_eq!(a.transfer(&a, 3), 0);
+        assert_eq!(b.transfer(&a, 0), 0);
+        assert_eq!(a.into_vec(), vec![4, 5]);
+        assert_eq!(b.into_vec(), vec![0, 1, 2, 3]);
+
+        // transfers both ways, among pushes and pops, neither deadlock
+        // nor lose anything
+        let pad = 1000;
+        for (a, b) in [
+            (MutexQueue::new(), MutexQueue::new()),
+            (MutexQueue::fair(), MutexQueue::fair()),
+        ] {
+            let (a, b) = (Arc::new(a), Arc::new(b));
+            for i in 0..5 {
+                a.push(i);
+            }
+            let threads: Vec<_> = [(a.clone(), b.clone()), (b.clone(), a.clone())]
+                .into_iter()
+                .enumerate()
+                .map(|(t, (src, dst))| {
+                    thread::spawn(move || {
+                        let mut popped = vec![];
+                        for i in 0..pad {
+                            src.push(5 + t * pad + i);
+                            src.transfer(&dst, 3);
+                            popped.extend(dst.pop());
+                        }
+                        popped
+                    })
+                })
+                .collect();
+            let mut all: Vec<_> = threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect();
+            all.extend(a.pop_while(|_| true));
+            all.extend(b.pop_while(|_| true));
+            all.sort();
+            assert_eq!(all, (0..5 + 2 * pad).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let pad = 100_000;
+
+        let q = MutexQueue::new();
+        for i in 0..pad {
+            q.push(i);
+        }
+        assert!(q.capacity() >= pad);
+        while q.pop().is_some() {}
+        assert!(q.capacity() >= pad);
+
+        q.shrink_to_fit();
+        assert!(q.capacity() < 16);
+
+        q.push(1);
+        q.push(4);
+        q.shrink_to_fit();
+        assert!(q.capacity() >= 2);
+        assert_eq!(q.into_vec(), vec![1, 4]);
+    }
+
+    #[test]
+    fn test_clone() {
+        let q = MutexQueue::from_vec(vec![1, 1, 4]);
+        let snapshot = q.clone();
+        assert_eq!(q, snapshot);
+
+        q.push(5);
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(snapshot.into_vec(), vec![1, 1, 4]);
+        assert_eq!(q.into_vec(), vec![1, 4, 5]);
+    }
+
+    #[test]
+    fn test_eq() {
+        let a = MutexQueue::from_vec(vec![1, 1, 4, 5, 1, 4]);
+        let b = MutexQueue::new();
+        for i in [1, 1, 4, 5, 1, 4] {
+            b.push(i);
+        }
+        assert_eq!(a, b);
+        assert_eq!(a, a);
+
+        // different order
+        let c = MutexQueue::from_vec(vec![1, 1, 4, 5, 4, 1]);
+        assert_ne!(a, c);
+        // different contents
+        b.pop();
+        assert_ne!(a, b);
+    }
+
     #[test]
     fn test_concurrent_send() {
         let pad = 100000_u128;
@@ -135,4 +890,120 @@ mod test {
         t3.join().unwrap();
         assert_eq!(sum, (0..(3 * pad)).sum());
     }
+
+    #[test]
+    fn test_fair() {
+        let q = MutexQueue::fair();
+        assert!(q.is_fair());
+        assert!(!MutexQueue::<i32>::new().is_fair());
+        q.push(1);
+        q.push(4);
+        assert_eq!(q.clone(), q);
+        assert!(q.clone().is_fair());
+        assert_eq!(q.pop_while(|_| true), vec![1, 4]);
+        assert_eq!(q.pop(), None);
+    }
+
+    #[test]
+    fn test_fair_progress() {
+        let producers = 8;
+        let pad = 200;
+
+        let q = Arc::new(MutexQueue::fair());
+        let ticket = q.ticket.as_ref().unwrap();
+
+        // hold the lock until every producer is lined up behind it
+        let held = q.lock();
+        let handles: Vec<_> = (0..producers)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push((n, i));
+                    }
+                })
+            })
+            .collect();
+        let backoff = Backoff::new();
+        while ticket.next.load(Ordering::SeqCst) != producers + 1 {
+            backoff.snooze();
+        }
+        drop(held);
+
+        for h in handles {
+            h.join().unwrap();
+        }
+        let pushed = Arc::try_unwrap(q).unwrap().into_vec();
+        assert_eq!(pushed.len(), producers * pad);
+
+        // served in arrival order, so nobody pushes twice before
+        // everyone already waiting has pushed once
+        let mut first: Vec<_> = pushed[..producers].iter().map(|&(n, _)| n).collect();
+        first.sort_unstable();
+        assert_eq!(first, (0..producers).collect::<Vec<_>>());
+
+        for n in 0..producers {
+            let mine: Vec<_> = pushed
+                .iter()
+                .filter(|(p, _)| *p == n)
+                .map(|&(_, i)| i)
+                .collect();
+            assert_eq!(mine, (0..pad).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_fair_poisoned() {
+        for q in [MutexQueue::new(), MutexQueue::fair()] {
+            let q = Arc::new(q);
+            q.push(1);
+            let p = q.clone();
+            let poisoner = thread::spawn(move || p.drain_in_place(|_| panic!("boom")));
+            assert!(poisoner.join().is_err());
+
+            // every later locker panics on the poison, in fair mode too,
+            // rather than waiting for a turn that never comes
+            for _ in 0..3 {
+                let l = q.clone();
+                assert!(thread::spawn(move || l.try_pop()).join().is_err());
+                let l = q.clone();
+                assert!(thread::spawn(move || l.len()).join().is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_poll_pop() {
+        let q = MutexQueue::new();
+        let noop = Waker::noop();
+        assert_eq!(q.poll_pop(noop), Poll::Pending);
+        q.push(1);
+        assert_eq!(q.poll_pop(noop), Poll::Ready(1));
+        assert_eq!(q.poll_pop(noop), Poll::Pending);
+    }
+
+    #[test]
+    fn test_poll_pop_wake() {
+        struct Count(AtomicUsize);
+        impl Wake for Count {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let count = Arc::new(Count(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+
+        let q = MutexQueue::new();
+        // polling twice registers once
+        assert_eq!(q.poll_pop(&waker), Poll::Pending);
+        assert_eq!(q.poll_pop(&waker), Poll::Pending);
+        q.push(1);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        // woken wakers are dropped, the next push wakes nobody
+        q.push(4);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(q.poll_pop(&waker), Poll::Ready(1));
+        assert_eq!(q.poll_pop(&waker), Poll::Ready(4));
+    }
 }
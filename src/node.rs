@@ -0,0 +1,53 @@
+// the list node shared by `CrsQueue` and `HeQueue`, which differ only in
+// how they move `tail`, so a list can be handed from one to the other
+
+use core::cell::UnsafeCell;
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crossbeam_epoch::Atomic;
+#[cfg(feature = "loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+// set on `Node::state` once a pop has claimed the node's item,
+// the remaining bits count the peekers currently reading it
+pub(crate) const TAKEN: usize = 1;
+pub(crate) const READER: usize = 2;
+
+pub(crate) type NodePtr<T> = Atomic<Node<T>>;
+
+pub(crate) struct Node<T> {
+    // only the pop whose head CAS retired this node's predecessor may take
+    // the item, and only after setting TAKEN and waiting out the peekers,
+    // so no reference into it is alive while it is written
+    pub item: UnsafeCell<Option<T>>,
+    pub next: NodePtr<T>,
+    pub state: AtomicUsize,
+}
+
+impl<T> Node<T> {
+    pub fn new_empty() -> Self {
+        Self {
+            item: UnsafeCell::new(None),
+            next: Atomic::null(),
+            state: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn new(data: T) -> Self {
+        Self {
+            item: UnsafeCell::new(Some(data)),
+            next: Atomic::null(),
+            state: AtomicUsize::new(0),
+        }
+    }
+}
+
+// releases a peeker's read on a node, even if the peek closure panics
+pub(crate) struct PeekGuard<'a>(pub &'a AtomicUsize);
+
+impl Drop for PeekGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(READER, Ordering::Release);
+    }
+}
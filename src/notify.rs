@@ -0,0 +1,92 @@
+// a minimal wakeup hook for consumers parked on an empty queue
+// producers only touch the lock when someone is actually waiting
+//
+// without `std` nobody can park, so it is zero sized and never notifies
+
+#[cfg(feature = "std")]
+use std::{
+    sync::{
+        atomic::{fence, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread::{self, Thread},
+};
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct Notify {
+    waiters: AtomicUsize,
+    threads: Mutex<Vec<Thread>>,
+}
+
+#[cfg(feature = "std")]
+impl Notify {
+    // register the current thread, must happen before the emptiness check
+    // that precedes parking, or a push in between may be missed
+    pub fn register(&self) {
+        let mut threads = self.threads.lock().unwrap();
+        threads.push(thread::current());
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        // pairs with the fence in `notify_*`: either the waiter's re-check
+        // sees the pushed item, or the producer sees the waiter
+        fence(Ordering::SeqCst);
+    }
+
+    // returns false if a `notify_one` already took us off the list,
+    // i.e. the wakeup was handed to this thread
+    pub fn unregister(&self) -> bool {
+        let id = thread::current().id();
+        let mut threads = self.threads.lock().unwrap();
+        if let Some(pos) = threads.iter().position(|t| t.id() == id) {
+            threads.swap_remove(pos);
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+
+    // must be called after the pushed item is visible to consumers
+    pub fn notify_all(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let threads = self.threads.lock().unwrap();
+        for t in threads.iter() {
+            t.unpark();
+        }
+    }
+
+    // wake the most recently registered thread and take it off the list,
+    // so that a burst of pushes wakes a different thread for each
+    pub fn notify_one(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut threads = self.threads.lock().unwrap();
+        if let Some(t) = threads.pop() {
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+            t.unpark();
+        }
+    }
+
+    // threads registered and not yet notified or unregistered
+    #[cfg(test)]
+    pub fn waiting(&self) -> usize {
+        self.waiters.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+#[derive(Default)]
+pub(crate) struct Notify {}
+
+#[cfg(not(feature = "std"))]
+impl Notify {
+    #[inline]
+    pub fn notify_all(&self) {}
+
+    #[inline]
+    pub fn notify_one(&self) {}
+}
@@ -0,0 +1,151 @@
+// a locked max-heap, for items that should be served by priority
+// rather than in arrival order
+
+use std::{
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+
+use crate::queue::Queue;
+
+/// A queue popping its greatest item first.
+///
+/// The method names match the FIFO queues', so it fits the [`Queue`]
+/// trait, but equal items come out in no particular order.
+#[derive(Debug)]
+pub struct PriorityQueue<T: Ord> {
+    inner: Mutex<BinaryHeap<T>>,
+}
+
+impl<T: Ord> Default for PriorityQueue<T> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(BinaryHeap::new()),
+        }
+    }
+}
+
+impl<T: Ord> PriorityQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new queue in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    pub fn len(&self) -> usize {
+        let guard = self.inner.lock().unwrap();
+        guard.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, item: T) {
+        let mut guard = self.inner.lock().unwrap();
+        guard.push(item);
+    }
+
+    /// Pop the greatest item.
+    pub fn pop(&self) -> Option<T> {
+        let mut guard = self.inner.lock().unwrap();
+        guard.pop()
+    }
+
+    /// A copy of the greatest item, which stays queued.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let guard = self.inner.lock().unwrap();
+        guard.peek().cloned()
+    }
+}
+
+impl<T: Ord + Send> Queue<T> for PriorityQueue<T> {
+    fn push(&self, item: T) {
+        self.push(item)
+    }
+    fn pop(&self) -> Option<T> {
+        self.pop()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod pq_test {
+    use std::{cmp::Reverse, sync::Arc, thread};
+
+    use crate::{priority_queue::PriorityQueue, queue::Queue};
+
+    #[test]
+    fn test_priority_order() {
+        let q = PriorityQueue::new();
+        assert!(q.is_empty());
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push(i);
+        }
+        assert_eq!(q.len(), 6);
+        let mut popped = vec![];
+        while let Some(i) = q.pop() {
+            popped.push(i);
+        }
+        assert_eq!(popped, vec![5, 4, 4, 1, 1, 1]);
+        assert_eq!(q.pop(), None);
+
+        // smallest first, through the trait
+        let q: Box<dyn Queue<Reverse<u32>>> = Box::new(PriorityQueue::new());
+        q.push(Reverse(3));
+        q.push(Reverse(1));
+        q.push(Reverse(2));
+        assert_eq!(q.pop(), Some(Reverse(1)));
+        assert_eq!(q.len(), 2);
+    }
+
+    #[test]
+    fn test_peek() {
+        let q = PriorityQueue::new();
+        assert_eq!(q.peek(), None);
+        q.push(String::from("a"));
+        q.push(String::from("c"));
+        q.push(String::from("b"));
+        assert_eq!(q.peek().as_deref(), Some("c"));
+        assert_eq!(q.peek().as_deref(), Some("c"));
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.pop().as_deref(), Some("c"));
+        assert_eq!(q.peek().as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_concurrent_push() {
+        let pad = if cfg!(miri) { 64 } else { 10_000u64 };
+
+        let q = Arc::new(PriorityQueue::new());
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push(i * 4 + n);
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let mut popped = vec![];
+        while let Some(i) = q.pop() {
+            popped.push(i);
+        }
+        assert_eq!(popped, (0..4 * pad).rev().collect::<Vec<_>>());
+    }
+}
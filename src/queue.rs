@@ -0,0 +1,195 @@
+// a common interface over the thread-safe queues, so the implementation
+// can be picked at runtime, e.g. from a config file
+
+use std::thread;
+
+use crate::{crs_queue::CrsQueue, he_queue::HeQueue, mutex_queue::MutexQueue};
+
+/// The operations every shareable queue in this crate supports.
+pub trait Queue<T>: Send + Sync {
+    fn push(&self, item: T);
+    fn pop(&self) -> Option<T>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Which implementation [`make_queue`] builds.
+///
+/// `LinkedQueue` is left out on purpose, it is a hand rolled baseline
+/// for the benches rather than a queue to build on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueKind {
+    /// [`CrsQueue`], lock-free with a casual tail.
+    Crs,
+    /// [`HeQueue`], lock-free with a strict tail.
+    He,
+    /// [`MutexQueue::new`], the generic locked choice, same as `Mutex`.
+    Lock,
+    /// [`MutexQueue::new`], locked with a plain `Mutex`.
+    Mutex,
+    /// [`MutexQueue::fair`], locked and handed out in arrival order,
+    /// trading throughput for it.
+    FairMutex,
+}
+
+impl QueueKind {
+    pub const ALL: [QueueKind; 5] = [
+        QueueKind::Crs,
+        QueueKind::He,
+        QueueKind::Lock,
+        QueueKind::Mutex,
+        QueueKind::FairMutex,
+    ];
+}
+
+/// Build an empty queue of the given kind.
+pub fn make_queue<T: Send + Sync + 'static>(kind: QueueKind) -> Box<dyn Queue<T>> {
+    match kind {
+        QueueKind::Crs => Box::new(CrsQueue::new()),
+        QueueKind::He => Box::new(HeQueue::new()),
+        QueueKind::Lock | QueueKind::Mutex => Box::new(MutexQueue::new()),
+        QueueKind::FairMutex => Box::new(MutexQueue::fair()),
+    }
+}
+
+/// Run `producer` and `consumer` on two scoped threads sharing `queue`,
+/// returning once both are done.
+///
+/// The queue is only borrowed, so no `Arc` is needed to share it.
+pub fn run_scoped<T, Q, F, G>(queue: &Q, producer: F, consumer: G)
+where
+    Q: Queue<T> + ?Sized,
+    F: FnOnce(&Q) + Send,
+    G: FnOnce(&Q) + Send,
+{
+    thread::scope(|s| {
+        s.spawn(|| producer(queue));
+        s.spawn(|| consumer(queue));
+    });
+}
+
+impl<T: Send + Sync> Queue<T> for CrsQueue<T> {
+    fn push(&self, item: T) {
+        self.push(item)
+    }
+    fn pop(&self) -> Option<T> {
+        self.pop()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T: Send + Sync> Queue<T> for HeQueue<T> {
+    fn push(&self, item: T) {
+        self.push(item)
+    }
+    fn pop(&self) -> Option<T> {
+        self.pop()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T: Send> Queue<T> for MutexQueue<T> {
+    fn push(&self, item: T) {
+        self.push(item)
+    }
+    fn pop(&self) -> Option<T> {
+        self.pop()
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod queue_test {
+    use std::{sync::Arc, thread};
+
+    use crate::queue::{make_queue, run_scoped, Queue, QueueKind};
+
+    #[test]
+    fn test_all_kinds() {
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        for kind in QueueKind::ALL {
+            let q = make_queue(kind);
+            assert!(q.is_empty(), "{:?}", kind);
+            for i in 0..pad {
+                q.push(i);
+            }
+            assert_eq!(q.len(), pad, "{:?}", kind);
+            for i in 0..pad {
+                assert_eq!(q.pop(), Some(i), "{:?}", kind);
+            }
+            assert_eq!(q.pop(), None, "{:?}", kind);
+            assert!(q.is_empty(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_all_kinds_shared() {
+        let pad = if cfg!(miri) { 64 } else { 10_000u64 };
+
+        for kind in QueueKind::ALL {
+            let q: Arc<dyn Queue<u64>> = make_queue(kind).into();
+            let p = q.clone();
+            let producer = thread::spawn(move || {
+                for i in 0..pad {
+                    p.push(i);
+                }
+            });
+            // a single consumer sees the single producer's order
+            let mut next = 0;
+            while next < pad {
+                if let Some(i) = q.pop() {
+                    assert_eq!(i, next, "{:?}", kind);
+                    next += 1;
+                }
+            }
+            producer.join().unwrap();
+            assert!(q.is_empty(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_run_scoped() {
+        for kind in QueueKind::ALL {
+            let q = make_queue(kind);
+            let mut sum = 0;
+            run_scoped(
+                &*q,
+                |q| {
+                    for i in 0..1000u64 {
+                        q.push(i);
+                    }
+                },
+                |q| {
+                    let mut popped = 0;
+                    while popped < 1000 {
+                        if let Some(i) = q.pop() {
+                            sum += i;
+                            popped += 1;
+                        }
+                    }
+                },
+            );
+            assert_eq!(sum, (0..1000).sum::<u64>(), "{:?}", kind);
+            assert!(q.is_empty(), "{:?}", kind);
+        }
+    }
+}
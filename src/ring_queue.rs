@@ -0,0 +1,178 @@
+// a fixed-size queue that can make room by evicting its oldest item,
+// for telemetry-style buffers where the newest data matters most
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug)]
+pub struct RingQueue<T> {
+    inner: Mutex<VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T> RingQueue<T> {
+    /// Create a ring holding at most `capacity` items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "ring capacity must be positive");
+        let inner = Mutex::new(VecDeque::with_capacity(capacity));
+        Self { inner, capacity }
+    }
+
+    /// [`new`](Self::new) in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared(capacity: usize) -> Arc<Self> {
+        Arc::new(Self::new(capacity))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many more items fit before `try_push` refuses them.
+    ///
+    /// A racy estimate, other threads may push or pop right after.
+    pub fn remaining_capacity(&self) -> usize {
+        self.capacity.saturating_sub(self.len())
+    }
+
+    pub fn len(&self) -> usize {
+        let guard = self.inner.lock().unwrap();
+        guard.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity
+    }
+
+    /// Push `item`, evicting and returning the oldest item if the ring is full.
+    pub fn push_overwrite(&self, item: T) -> Option<T> {
+        let mut guard = self.inner.lock().unwrap();
+        let evicted = if guard.len() == self.capacity {
+            guard.pop_front()
+        } else {
+            None
+        };
+        guard.push_back(item);
+        evicted
+    }
+
+    /// Push `item` unless the ring is full, in which case it is handed back.
+    #[must_use = "a refused item is handed back in the error, and dropped if ignored"]
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        let mut guard = self.inner.lock().unwrap();
+        if guard.len() == self.capacity {
+            return Err(item);
+        }
+        guard.push_back(item);
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let mut guard = self.inner.lock().unwrap();
+        guard.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod ring_test {
+    use std::{sync::Arc, thread};
+
+    use super::RingQueue;
+
+    #[test]
+    fn test_push_overwrite() {
+        let q = RingQueue::new(3);
+        assert_eq!(q.push_overwrite(1), None);
+        assert_eq!(q.push_overwrite(1), None);
+        assert_eq!(q.push_overwrite(4), None);
+        assert!(q.is_full());
+        assert_eq!(q.push_overwrite(5), Some(1));
+        assert_eq!(q.push_overwrite(1), Some(1));
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.pop(), Some(4));
+        assert_eq!(q.push_overwrite(4), None);
+        assert_eq!(q.pop(), Some(5));
+        assert_eq!(q.pop(), Some(1));
+        assert_eq!(q.pop(), Some(4));
+        assert_eq!(q.pop(), None);
+    }
+
+    #[test]
+    fn test_try_push() {
+        let q = RingQueue::new(2);
+        assert_eq!(q.try_push(String::from("1")), Ok(()));
+        assert_eq!(q.try_push(String::from("4")), Ok(()));
+        assert_eq!(q.try_push(String::from("5")), Err(String::from("5")));
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.pop().as_deref(), Some("1"));
+        assert_eq!(q.try_push(String::from("5")), Ok(()));
+        assert_eq!(q.pop().as_deref(), Some("4"));
+        assert_eq!(q.pop().as_deref(), Some("5"));
+    }
+
+    #[test]
+    fn test_try_push_returns_same_item() {
+        let q = RingQueue::new(1);
+        q.try_push(Box::new(1)).unwrap();
+        let item = Box::new(4);
+        let addr: *const i32 = &*item;
+        let back = q.try_push(item).unwrap_err();
+        assert!(std::ptr::eq(addr, &*back));
+        assert_eq!(q.pop(), Some(Box::new(1)));
+    }
+
+    #[test]
+    fn test_remaining_capacity() {
+        let q = RingQueue::new(4);
+        assert_eq!(q.remaining_capacity(), 4);
+        q.try_push(1).unwrap();
+        q.try_push(4).unwrap();
+        assert_eq!(q.remaining_capacity(), 2);
+        for i in 0..3 {
+            q.push_overwrite(i);
+        }
+        assert_eq!(q.remaining_capacity(), 0);
+        q.pop();
+        assert_eq!(q.remaining_capacity(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_capacity() {
+        RingQueue::<i32>::new(0);
+    }
+
+    #[test]
+    fn test_concurrent_overwrite() {
+        let cap = 16;
+        let pad = 10_000;
+
+        let q = Arc::new(RingQueue::new(cap));
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut evicted = 0;
+                    for i in 0..pad {
+                        if q.push_overwrite(n * pad + i).is_some() {
+                            evicted += 1;
+                        }
+                    }
+                    evicted
+                })
+            })
+            .collect();
+        let evicted: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(q.len(), cap);
+        assert_eq!(evicted, 4 * pad - cap);
+    }
+}
@@ -0,0 +1,181 @@
+// several `HeQueue`s side by side, so producers spread over as many tails
+// instead of all racing on one
+//
+// each thread is handed a shard index the first time it pushes, and keeps
+// pushing there; pops start at a rotating shard and scan the others
+
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use crate::he_queue::HeQueue;
+
+// the next index handed to a thread, shared by every `ShardedQueue`
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn thread_index() -> usize {
+    THREAD_INDEX.with(|i| match i.get() {
+        Some(i) => i,
+        None => {
+            let next = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+            i.set(Some(next));
+            next
+        }
+    })
+}
+
+/// A queue split into shards, each a [`HeQueue`] of its own.
+///
+/// Items pushed by one thread go to the same shard and come out in the
+/// order they were pushed, but there is no order across shards: an item
+/// may be popped before one pushed earlier to another shard.
+pub struct ShardedQueue<T> {
+    shards: Box<[HeQueue<T>]>,
+    // where the next pop starts scanning
+    pop_from: AtomicUsize,
+}
+
+// a shard per core
+impl<T> Default for ShardedQueue<T> {
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+impl<T> ShardedQueue<T> {
+    /// Create a queue with `shards` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "a sharded queue needs at least one shard");
+        Self {
+            shards: (0..shards).map(|_| HeQueue::new()).collect(),
+            pop_from: AtomicUsize::new(0),
+        }
+    }
+
+    /// [`new`](Self::new) in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared(shards: usize) -> Arc<Self> {
+        Arc::new(Self::new(shards))
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The sum of the shards' lengths, as racy as each of them.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(HeQueue::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(HeQueue::is_empty)
+    }
+
+    /// Push `item` to the calling thread's shard.
+    pub fn push(&self, item: T) {
+        let shard = thread_index() % self.shards.len();
+        self.shards[shard].push(item);
+    }
+
+    /// Pop from the first shard with an item, starting one further along
+    /// than the previous pop.
+    pub fn pop(&self) -> Option<T> {
+        let n = self.shards.len();
+        let start = self.pop_from.fetch_add(1, Ordering::Relaxed);
+        (0..n).find_map(|i| self.shards[(start + i) % n].pop())
+    }
+}
+
+#[cfg(test)]
+mod sq_test {
+    use std::{sync::Arc, thread};
+
+    use crate::sharded_queue::ShardedQueue;
+
+    #[test]
+    fn test_single() {
+        let q = ShardedQueue::new(4);
+        assert_eq!(q.shards(), 4);
+        assert!(q.is_empty());
+        // one thread, one shard, so still in order
+        for i in [1, 1, 4, 5, 1, 4] {
+            q.push(i);
+        }
+        assert_eq!(q.len(), 6);
+        for i in [1, 1, 4, 5, 1, 4] {
+            assert_eq!(q.pop(), Some(i));
+        }
+        assert_eq!(q.pop(), None);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_no_shards() {
+        ShardedQueue::<i32>::new(0);
+    }
+
+    #[test]
+    fn test_mpmc() {
+        let producers = 8;
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        let q = Arc::new(ShardedQueue::new(4));
+        let handles: Vec<_> = (0..producers)
+            .map(|p| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..pad {
+                        q.push((p, i));
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut got = vec![];
+                    while got.len() < producers * pad / 2 {
+                        if let Some(item) = q.pop() {
+                            got.push(item);
+                        }
+                    }
+                    got
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let logs: Vec<_> = consumers.into_iter().map(|c| c.join().unwrap()).collect();
+        assert!(q.is_empty());
+
+        // nothing lost or duplicated, and each consumer sees every
+        // producer's items in order, as they share a shard
+        for log in &logs {
+            for p in 0..producers {
+                let mine: Vec<_> = log.iter().filter(|(q, _)| *q == p).collect();
+                assert!(mine.windows(2).all(|w| w[0].1 < w[1].1));
+            }
+        }
+        let mut all: Vec<_> = logs.into_iter().flatten().collect();
+        all.sort();
+        let expected: Vec<_> = (0..producers)
+            .flat_map(|p| (0..pad).map(move |i| (p, i)))
+            .collect();
+        assert_eq!(all, expected);
+    }
+}
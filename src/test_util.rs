@@ -0,0 +1,212 @@
+// shared helpers for the queue tests: replay operation sequences against
+// a `VecDeque` model, and check logs recorded by concurrent threads
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use proptest::prelude::*;
+
+use crate::queue::Queue;
+
+// counts the allocations made by the thread inside `allocations`,
+// other threads and tests are unaffected
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCS: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // `try_with`, as the thread local may already be gone at thread exit
+        let _ = ALLOCS.try_with(|n| n.set(n.get().map(|n| n + 1)));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+// how many allocations `f` made on the current thread
+pub fn allocations(f: impl FnOnce()) -> usize {
+    ALLOCS.with(|n| n.set(Some(0)));
+    f();
+    ALLOCS.with(|n| n.take()).unwrap()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Push(u32),
+    Pop,
+}
+
+// what a single-threaded caller sees after each operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    pub popped: Option<u32>,
+    pub len: usize,
+    pub is_empty: bool,
+}
+
+pub fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![any::<u32>().prop_map(Op::Push), Just(Op::Pop)]
+}
+
+pub fn ops(max: usize) -> impl Strategy<Value = Vec<Op>> {
+    prop::collection::vec(op(), 0..max)
+}
+
+pub fn replay<Q: Queue<u32>>(q: &Q, ops: &[Op]) -> Vec<Observation> {
+    ops.iter()
+        .map(|op| {
+            let popped = match *op {
+                Op::Push(i) => {
+                    q.push(i);
+                    None
+                }
+                Op::Pop => q.pop(),
+            };
+            Observation {
+                popped,
+                len: q.len(),
+                is_empty: q.is_empty(),
+            }
+        })
+        .collect()
+}
+
+pub fn replay_model(ops: &[Op]) -> Vec<Observation> {
+    let mut model = VecDeque::new();
+    ops.iter()
+        .map(|op| {
+            let popped = match *op {
+                Op::Push(i) => {
+                    model.push_back(i);
+                    None
+                }
+                Op::Pop => model.pop_front(),
+            };
+            Observation {
+                popped,
+                len: model.len(),
+                is_empty: model.is_empty(),
+            }
+        })
+        .collect()
+}
+
+// items pushed concurrently are tagged `(producer, seq)`, with `seq` counting
+// up per producer; every consumer logs what it popped in order
+//
+// checks that all `per_producer` items of every producer were popped exactly
+// once, and that no consumer saw a producer's items out of order
+pub fn check_logs(producers: usize, per_producer: usize, consumers: &[Vec<(usize, usize)>]) {
+    let mut seen = HashMap::new();
+    for log in consumers {
+        let mut last: HashMap<usize, usize> = HashMap::new();
+        for &(p, seq) in log {
+            assert!(
+                p < producers && seq < per_producer,
+                "unknown item {:?}",
+                (p, seq)
+            );
+            if let Some(&prev) = last.get(&p) {
+                assert!(
+                    prev < seq,
+                    "producer {} out of order: {} after {}",
+                    p,
+                    seq,
+                    prev
+                );
+            }
+            last.insert(p, seq);
+            *seen.entry((p, seq)).or_insert(0) += 1;
+        }
+    }
+    for p in 0..producers {
+        for seq in 0..per_producer {
+            assert_eq!(seen.get(&(p, seq)), Some(&1), "item {:?}", (p, seq));
+        }
+    }
+}
+
+// runs `threads / 2` producers and as many consumers on `q`, sampling `len`
+// meanwhile; every sample must lie between what had certainly been pushed
+// and not popped, and what had at most been pushed and not popped, so a
+// counter running off or wrapping below zero shows up as out of bounds
+pub fn check_len_bounds<Q: Queue<usize> + 'static>(q: Arc<Q>, threads: usize, per_thread: usize) {
+    let push_started = Arc::new(AtomicUsize::new(0));
+    let push_done = Arc::new(AtomicUsize::new(0));
+    let pop_started = Arc::new(AtomicUsize::new(0));
+    let pop_done = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let mut handles = vec![];
+    for _ in 0..threads / 2 {
+        let (q, started, done) = (q.clone(), push_started.clone(), push_done.clone());
+        handles.push(thread::spawn(move || {
+            for i in 0..per_thread {
+                started.fetch_add(1, Ordering::SeqCst);
+                q.push(i);
+                done.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+    }
+    for _ in 0..threads / 2 {
+        let (q, started, done) = (q.clone(), pop_started.clone(), pop_done.clone());
+        let stop = stop.clone();
+        handles.push(thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                // counted as started only when it may succeed, a pop
+                // finding nothing never touches the counter
+                started.fetch_add(1, Ordering::SeqCst);
+                if q.pop().is_some() {
+                    done.fetch_add(1, Ordering::SeqCst);
+                } else {
+                    started.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        }));
+    }
+
+    let total = threads / 2 * per_thread;
+    let mut samples = 0;
+    while pop_done.load(Ordering::SeqCst) < total {
+        // the lower bound's counters before `len` and the upper bound's
+        // after, so both only loosen while we read
+        let popped = pop_done.load(Ordering::SeqCst);
+        let pushed = push_done.load(Ordering::SeqCst);
+        let len = q.len();
+        let push_begun = push_started.load(Ordering::SeqCst);
+        let pop_begun = pop_started.load(Ordering::SeqCst);
+
+        assert!(len <= total, "len {len} wrapped or ran off");
+        assert!(
+            len <= push_begun - popped,
+            "len {len} above {push_begun} pushes begun - {popped} pops done"
+        );
+        assert!(
+            len >= pushed.saturating_sub(pop_begun),
+            "len {len} below {pushed} pushes done - {pop_begun} pops begun"
+        );
+        samples += 1;
+    }
+    stop.store(true, Ordering::SeqCst);
+    for h in handles {
+        h.join().unwrap();
+    }
+    assert!(samples > 0);
+    assert_eq!(q.len(), 0);
+}
@@ -0,0 +1,115 @@
+// a lock-free stack of wakers for tasks waiting on an empty queue
+//
+// waiters only ever push, and wakers take the whole stack at once with a
+// swap, so no node is ever read by one thread while another frees it,
+// and a recycled address can't fool the push CAS
+//
+// each task registers through a slot of its own, which is on the stack at
+// most once, so polling again and again before a wake doesn't grow it
+
+use std::{
+    mem, ptr,
+    sync::{
+        atomic::{fence, AtomicPtr, Ordering},
+        Arc, Mutex,
+    },
+    task::Waker,
+};
+
+// a task's place in a `WakerList`, holding the waker from its latest poll
+#[derive(Default)]
+pub(crate) struct WakerSlot(Mutex<SlotState>);
+
+#[derive(Default)]
+struct SlotState {
+    waker: Option<Waker>,
+    // on the stack, cleared by the wake that takes it off
+    listed: bool,
+}
+
+struct WakerNode {
+    slot: Arc<WakerSlot>,
+    next: *mut WakerNode,
+}
+
+pub(crate) struct WakerList {
+    head: AtomicPtr<WakerNode>,
+}
+
+impl Default for WakerList {
+    fn default() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl WakerList {
+    // must happen before the emptiness re-check that precedes `Pending`
+    pub fn register(&self, slot: &Arc<WakerSlot>, waker: &Waker) {
+        let listed = {
+            let mut state = slot.0.lock().unwrap();
+            if !state.waker.as_ref().is_some_and(|w| w.will_wake(waker)) {
+                state.waker = Some(waker.clone());
+            }
+            mem::replace(&mut state.listed, true)
+        };
+        // still listed, the wake that takes it off will see the new waker
+        if !listed {
+            self.push(slot.clone());
+        }
+        // pairs with the fence in `wake_all`: either the waiter's re-check
+        // sees the new item, or the producer sees the waiter
+        fence(Ordering::SeqCst);
+    }
+
+    fn push(&self, slot: Arc<WakerSlot>) {
+        let node = Box::into_raw(Box::new(WakerNode {
+            slot,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // the node isn't shared until the CAS succeeds
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    // must be called after the pushed item is visible to consumers
+    pub fn wake_all(&self) {
+        fence(Ordering::SeqCst);
+        if self.head.load(Ordering::Relaxed).is_null() {
+            return;
+        }
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        while !node.is_null() {
+            let taken = unsafe { Box::from_raw(node) };
+            node = taken.next;
+            let waker = {
+                let mut state = taken.slot.0.lock().unwrap();
+                state.listed = false;
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for WakerList {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let taken = unsafe { Box::from_raw(node) };
+            node = taken.next;
+        }
+    }
+}
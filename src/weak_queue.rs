@@ -0,0 +1,132 @@
+// a `CrsQueue` of weak references, for consumers that only care about
+// entries whose target is still alive
+
+use std::sync::{Arc, Weak};
+
+use crate::crs_queue::CrsQueue;
+
+/// A queue of [`Weak`]s whose `pop` skips the dead ones.
+pub struct WeakQueue<T> {
+    inner: CrsQueue<Weak<T>>,
+}
+
+impl<T> Default for WeakQueue<T> {
+    fn default() -> Self {
+        Self {
+            inner: CrsQueue::new(),
+        }
+    }
+}
+
+impl<T> WeakQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new queue in an [`Arc`], ready to be cloned into other threads.
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    /// Queued entries, dead ones included until a pop runs into them.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn push(&self, item: Weak<T>) {
+        self.inner.push(item);
+    }
+
+    /// Pop the first entry still alive, dropping the dead ones before it.
+    pub fn pop(&self) -> Option<Arc<T>> {
+        while let Some(weak) = self.inner.pop() {
+            if let Some(item) = weak.upgrade() {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod wq_test {
+    use std::{sync::Arc, thread};
+
+    use crate::weak_queue::WeakQueue;
+
+    #[test]
+    fn test_skip_dead() {
+        let q = WeakQueue::new();
+        let items: Vec<_> = (0..6).map(Arc::new).collect();
+        for item in &items {
+            q.push(Arc::downgrade(item));
+        }
+        assert_eq!(q.len(), 6);
+
+        // keep 1, 4 and 5
+        let live: Vec<_> = items
+            .into_iter()
+            .filter(|i| [1, 4, 5].contains(&**i))
+            .collect();
+        for i in [1, 4, 5] {
+            assert_eq!(q.pop().as_deref(), Some(&i));
+        }
+        assert_eq!(q.pop(), None);
+        assert!(q.is_empty());
+
+        // a dead tail is dropped on the way to `None`
+        let gone = Arc::new(0);
+        q.push(Arc::downgrade(&gone));
+        drop(gone);
+        assert_eq!(q.len(), 1);
+        assert_eq!(q.pop(), None);
+        assert!(q.is_empty());
+        drop(live);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let pad = if cfg!(miri) { 64 } else { 10_000 };
+
+        let q = Arc::new(WeakQueue::new());
+        let items: Vec<_> = (0..pad).map(Arc::new).collect();
+        for item in &items {
+            q.push(Arc::downgrade(item));
+        }
+        // the odd ones die while consumers are popping
+        let live: Vec<_> = items
+            .iter()
+            .filter(|i| i.as_ref() % 2 == 0)
+            .cloned()
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut got = vec![];
+                    while let Some(i) = q.pop() {
+                        got.push(*i);
+                    }
+                    got
+                })
+            })
+            .collect();
+        drop(items);
+
+        let mut got: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        got.sort();
+        // nothing twice, and every live item is handed out, the odd ones
+        // only if they were popped before they died
+        assert!(got.windows(2).all(|w| w[0] < w[1]));
+        let evens: Vec<_> = got.iter().copied().filter(|i| i % 2 == 0).collect();
+        assert_eq!(evens, (0..pad).step_by(2).collect::<Vec<_>>());
+        drop(live);
+    }
+}
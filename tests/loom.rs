@@ -0,0 +1,122 @@
+// explore `HeQueue` interleavings under the C11 memory model, 2P1C and 1P2C,
+// with either ordering profile, and `LinkedQueue`'s 1P2C, run with
+// RUSTFLAGS="--cfg crossbeam_loom" cargo test --release --features loom --test loom
+#![cfg(feature = "loom")]
+
+use l3queue::{
+    he_queue::{HeQueue, OrderingProfile},
+    lq::LinkedQueue,
+};
+use loom::{alloc::Track, model::Builder, sync::Arc, thread};
+
+const PROFILES: [OrderingProfile; 2] = [OrderingProfile::Strong, OrderingProfile::Relaxed];
+
+#[test]
+fn loom_two_producers_one_consumer() {
+    // epoch pinning alone adds dozens of atomic steps per operation,
+    // so preemptions are bounded to keep the search tractable,
+    // raise it with LOOM_MAX_PREEMPTIONS for a deeper (much slower) run
+    for orderings in PROFILES {
+        let mut model = Builder::new();
+        model.preemption_bound = model.preemption_bound.or(Some(1));
+        model.check(move || {
+            let q = Arc::new(HeQueue::with_orderings(orderings));
+
+            let producers: Vec<_> = [1, 2]
+                .into_iter()
+                .map(|i| {
+                    let p = q.clone();
+                    thread::spawn(move || p.push(i))
+                })
+                .collect();
+
+            // pop concurrently with the producers, whatever they managed to publish
+            let mut got = vec![];
+            got.extend(q.pop());
+
+            for p in producers {
+                p.join().unwrap();
+            }
+            while let Some(i) = q.pop() {
+                got.push(i);
+            }
+
+            // nothing lost, nothing duplicated
+            got.sort_unstable();
+            assert_eq!(got, vec![1, 2]);
+            assert_eq!(q.size(), 0);
+        });
+    }
+}
+
+#[test]
+fn loom_one_producer_two_consumers() {
+    for orderings in PROFILES {
+        let mut model = Builder::new();
+        model.preemption_bound = model.preemption_bound.or(Some(1));
+        model.check(move || {
+            let q = Arc::new(HeQueue::with_orderings(orderings));
+
+            // two pops racing for the front node, and then for its successor,
+            // retire nodes the loser may still be looking at
+            let consumers: Vec<_> = (0..2)
+                .map(|_| {
+                    let c = q.clone();
+                    thread::spawn(move || c.pop())
+                })
+                .collect();
+
+            q.push(1);
+            q.push(2);
+
+            let mut got: Vec<_> = consumers
+                .into_iter()
+                .filter_map(|c| c.join().unwrap())
+                .collect();
+            while let Some(i) = q.pop() {
+                got.push(i);
+            }
+
+            // nothing lost, nothing duplicated
+            got.sort_unstable();
+            assert_eq!(got, vec![1, 2]);
+            assert_eq!(q.size(), 0);
+        });
+    }
+}
+
+// the race that used to hand one item to both consumers and free a node
+// the loser was still reading: a lost or doubled item shows in the
+// assertions, and `Track` makes loom fail on any item never dropped
+#[test]
+fn loom_lq_one_producer_two_consumers() {
+    let mut model = Builder::new();
+    model.preemption_bound = model.preemption_bound.or(Some(2));
+    model.check(|| {
+        let q = Arc::new(LinkedQueue::new());
+
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let c = q.clone();
+                thread::spawn(move || c.pop().map(Track::into_inner))
+            })
+            .collect();
+
+        for i in 1..=3 {
+            q.push(Track::new(i));
+        }
+
+        let mut got: Vec<_> = consumers
+            .into_iter()
+            .filter_map(|c| c.join().unwrap())
+            .collect();
+        while let Some(i) = q.pop() {
+            got.push(i.into_inner());
+        }
+
+        // nothing lost, nothing duplicated
+        got.sort_unstable();
+        assert_eq!(got, vec![1, 2, 3]);
+        assert!(q.is_empty());
+    });
+}
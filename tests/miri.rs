@@ -0,0 +1,219 @@
+// small single-threaded push/pop sequences over the epoch-based queues,
+// meant for miri to check node handling and the `Drop` impls, run with
+// MIRIFLAGS="-Zmiri-ignore-leaks -Zmiri-permissive-provenance" cargo +nightly miri test --test miri
+//
+// the global epoch collector still holds deferred nodes at exit, which
+// miri would report as leaks, so leaked items are caught by counting
+// drops instead; crossbeam-epoch casts integers to pointers, hence the
+// permissive provenance
+//
+// nothing here is timing dependent, so it also runs as a plain test
+#![cfg(feature = "std")]
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use l3queue::{crs_queue::CrsQueue, he_queue::HeQueue};
+
+// counts its drops, so double drops and leaked items both show
+struct Tracked {
+    id: usize,
+    drops: Rc<Cell<usize>>,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
+
+fn tracked(n: usize, drops: &Rc<Cell<usize>>) -> impl Iterator<Item = Tracked> + '_ {
+    (0..n).map(move |id| Tracked {
+        id,
+        drops: drops.clone(),
+    })
+}
+
+#[test]
+fn crs_strings() {
+    let q = CrsQueue::new();
+    for s in ["1", "1", "4"] {
+        q.push(String::from(s));
+    }
+    assert_eq!(q.pop().as_deref(), Some("1"));
+    q.push(String::from("5"));
+    assert_eq!(q.peek(|s| s.clone()).as_deref(), Some("1"));
+    assert_eq!(q.pop().as_deref(), Some("1"));
+    assert_eq!(q.pop().as_deref(), Some("4"));
+    assert_eq!(q.pop().as_deref(), Some("5"));
+    assert_eq!(q.pop(), None);
+    assert!(q.is_empty());
+    // push again after running dry
+    q.push(String::from("1"));
+    q.push(String::from("4"));
+    assert_eq!(q.len(), 2);
+}
+
+#[test]
+fn crs_boxes_left_on_drop() {
+    let q = CrsQueue::new();
+    q.prewarm(4);
+    for i in 0..8 {
+        q.push(Box::new(i));
+    }
+    let mut buf = vec![];
+    assert_eq!(q.pop_into(&mut buf, 3), 3);
+    assert_eq!(buf, [Box::new(0), Box::new(1), Box::new(2)]);
+    // the other five go with the queue
+}
+
+#[test]
+fn crs_drop_counts() {
+    let drops = Rc::new(Cell::new(0));
+    {
+        let q = CrsQueue::new();
+        for t in tracked(6, &drops) {
+            q.push(t);
+        }
+        assert_eq!(q.pop().map(|t| t.id), Some(0));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(q.pop().map(|t| t.id), Some(1));
+    }
+    assert_eq!(drops.get(), 6);
+}
+
+#[test]
+fn crs_arcs() {
+    let q = CrsQueue::new();
+    let item = Arc::new(Mutex::new(String::from("114")));
+    q.push_arc(item.clone());
+    q.push_arc(Arc::new(Mutex::new(String::from("514"))));
+    let got = q.pop_arc().unwrap();
+    assert!(Arc::ptr_eq(&got, &item));
+    drop(q);
+    assert_eq!(Arc::strong_count(&item), 2);
+}
+
+#[test]
+fn crs_into_he_and_back() {
+    let q = CrsQueue::new();
+    for s in ["1", "4"] {
+        q.push(String::from(s));
+    }
+    let q: HeQueue<_> = q.into();
+    q.push(String::from("5"));
+    let q: CrsQueue<_> = q.into();
+    assert_eq!(q.pop().as_deref(), Some("1"));
+    assert_eq!(q.pop().as_deref(), Some("4"));
+    assert_eq!(q.pop().as_deref(), Some("5"));
+}
+
+#[test]
+fn he_strings() {
+    let q = HeQueue::new();
+    for s in ["1", "1", "4"] {
+        q.push(String::from(s));
+    }
+    assert_eq!(q.pop().as_deref(), Some("1"));
+    q.push(String::from("5"));
+    assert_eq!(q.peek().as_deref(), Some("1"));
+    assert_eq!(q.peek_n(2), ["1", "4"]);
+    assert_eq!(q.pop().as_deref(), Some("1"));
+    assert_eq!(q.pop().as_deref(), Some("4"));
+    assert_eq!(q.pop().as_deref(), Some("5"));
+    assert_eq!(q.pop(), None);
+    assert!(q.is_empty());
+    q.push(String::from("1"));
+    assert_eq!(q.len(), 1);
+}
+
+#[test]
+fn he_boxes_left_on_drop() {
+    let q = HeQueue::new();
+    q.push_batch((0..8).map(Box::new));
+    assert_eq!(q.pop_batch(3), [Box::new(0), Box::new(1), Box::new(2)]);
+    let other: HeQueue<_> = (8..10).map(Box::new).collect();
+    q.append(other);
+    assert_eq!(q.len(), 7);
+}
+
+#[test]
+fn he_drop_counts() {
+    let drops = Rc::new(Cell::new(0));
+    {
+        let mut q = HeQueue::new();
+        q.extend(tracked(6, &drops));
+        assert_eq!(q.pop().map(|t| t.id), Some(0));
+        assert_eq!(drops.get(), 1);
+        // popped in place of the front, refused ones stay queued
+        assert_eq!(q.pop_if(|t| t.id == 1).map(|t| t.id), Some(1));
+        assert!(q.pop_if(|t| t.id == 3).is_none());
+        assert_eq!(drops.get(), 2);
+    }
+    assert_eq!(drops.get(), 6);
+}
+
+#[test]
+fn he_drain_and_retain() {
+    let drops = Rc::new(Cell::new(0));
+    let mut q = HeQueue::new();
+    q.extend(tracked(8, &drops));
+    q.retain(|t| t.id % 2 == 0);
+    assert_eq!(drops.get(), 4);
+    let ids: Vec<_> = q.iter().map(|t| t.id).collect();
+    assert_eq!(ids, [0, 2, 4, 6]);
+
+    // a drain dropped halfway leaves the rest queued
+    let mut drain = q.drain();
+    assert_eq!(drain.next().map(|t| t.id), Some(0));
+    drop(drain);
+    assert_eq!(drops.get(), 5);
+    let ids: Vec<_> = q.into_iter().map(|t| t.id).collect();
+    assert_eq!(ids, [2, 4, 6]);
+    assert_eq!(drops.get(), 8);
+}
+
+#[test]
+fn he_try_push_refused() {
+    let q = HeQueue::with_capacity(1);
+    q.try_push(String::from("1")).unwrap();
+    let back = q.try_push(String::from("4")).unwrap_err().into_inner();
+    assert_eq!(back, "4");
+    q.close();
+    assert!(q.try_push(String::from("5")).is_err());
+    assert_eq!(q.pop().as_deref(), Some("1"));
+}
+
+#[test]
+fn he_spsc() {
+    let (mut tx, mut rx) = HeQueue::new().split_spsc();
+    for s in ["1", "1", "4"] {
+        tx.push(String::from(s));
+    }
+    assert_eq!(rx.pop().as_deref(), Some("1"));
+    tx.push(String::from("5"));
+    assert_eq!(rx.pop().as_deref(), Some("1"));
+    // the rest go with the halves
+}
+
+#[test]
+fn he_walk() {
+    let q: HeQueue<_> = (0..4).map(Box::new).collect();
+    q.pop();
+    let report = q.walk();
+    assert_eq!(report.nodes, 3);
+}
+
+// printing the nodes' addresses is fine under miri too
+#[test]
+fn crs_walk() {
+    let q = CrsQueue::new();
+    for i in 0..4 {
+        q.push(Box::new(i));
+    }
+    q.pop();
+    q.walk();
+}